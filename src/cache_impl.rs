@@ -1,47 +1,418 @@
+use super::config::CacheConfig;
 use super::detail::*;
+use super::observer::CacheObserver;
+use super::stats::CacheStats;
 use super::*;
 
-use std::io::{Read, Seek};
+use std::io::Write;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 
-pub struct CacheImpl<Source: Read + Seek, S: Sets> {
-    #[allow(dead_code)]
-    source: Source,
-    #[allow(dead_code)]
-    sets: S,
+const NULL: usize = usize::MAX;
+const NIL: u64 = u64::MAX;
+
+type SetOf<Config> = <<Config as CacheConfig>::S as Sets>::S;
+pub type ReplaceOf<Config> = <SetOf<Config> as Set>::R;
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    writebacks: AtomicU64,
+    source_errors: AtomicU64,
+    admission_rejects: AtomicU64,
 }
 
-impl<Source: Read + Seek, S: Sets> CacheImpl<Source, S> {
+pub struct CacheImpl<Config: CacheConfig> {
+    // Only taken by `into_inner`, so `drop` knows the blocks were already flushed.
+    io: Option<Config::IO>,
+    sets: Config::S,
+    observer: Config::Observer,
+    counters: Counters,
+    len: AtomicU64,
+}
+
+impl<Config: CacheConfig> CacheImpl<Config> {
+    const BLOCK_SIZE: u64 = Config::BlockSize::VALUE as u64;
+
     // Panic if mem isn't enough to hold one set.
     // Panic if CacheConfig is an invalid configuration.
-    pub fn new(source: Source, mem: usize) -> Self {
-        Self {
-            source,
-            sets: S::new(mem),
-        }
+    // Panic if the source can't be opened.
+    pub fn new(source: Config::Source, mem: usize, observer: Config::Observer) -> Self {
+        Self::with_sets(source, Config::S::new(mem), observer)
     }
 
     // Panic if mem isn't enough to hold one set and meta data.
     // Panic if CacheConfig is an invalid configuration.
-    pub fn new_strict(source: Source, mem: usize) -> Self {
+    // Panic if the source can't be opened.
+    pub fn new_strict(source: Config::Source, mem: usize, observer: Config::Observer) -> Self {
+        Self::with_sets(source, Config::S::new_strict(mem), observer)
+    }
+
+    fn with_sets(source: Config::Source, sets: Config::S, observer: Config::Observer) -> Self {
+        let io = Config::IO::new(source).expect("io_cache failed to open the source");
+        let len = io.len();
         Self {
-            source,
-            sets: S::new_strict(mem),
+            io: Some(io),
+            sets,
+            observer,
+            counters: Counters::default(),
+            len: AtomicU64::new(len),
         }
     }
 
-    pub fn into_inner(self) -> Source {
-        unimplemented!()
+    pub fn observer(&self) -> &Config::Observer {
+        &self.observer
     }
 
-    pub fn read_chunks<R: RangeBounds<u64>, F: FnMut(&[u8])>(&self, _range: R, _f: F) {
-        unimplemented!()
+    pub fn io_latency(&self) -> Option<&IOLatency> {
+        self.io().latency()
     }
 
-    pub fn read<R: RangeBounds<u64>>(&self, _range: R, _buf: &mut [u8]) -> usize {
-        unimplemented!()
+    pub fn for_each_replace<F: FnMut(&mut ReplaceOf<Config>)>(&self, mut f: F) {
+        for idx in 0..self.sets.count() {
+            self.sets.set_at(idx).write(|set| f(set.replace_mut()));
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut ret = CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+            writebacks: self.counters.writebacks.load(Ordering::Relaxed),
+            source_errors: self.counters.source_errors.load(Ordering::Relaxed),
+            admission_rejects: self.counters.admission_rejects.load(Ordering::Relaxed),
+            data_mem: self.sets.data_mem(),
+            meta_mem: self.sets.meta_mem(),
+            queue_depth: self.io().queue_depth(),
+            ..Default::default()
+        };
+        for idx in 0..self.sets.count() {
+            self.sets.set_at(idx).read(|set| {
+                ret.blocks += set.count();
+                let (hits, predictions) = set.lookup().way_predictions();
+                ret.way_prediction_hits += hits;
+                ret.way_predictions += predictions;
+                for frame in 0..set.count() {
+                    let meta = set.meta(frame);
+                    if meta.page != NIL {
+                        ret.valid_blocks += 1;
+                        if meta.dirty {
+                            ret.dirty_blocks += 1;
+                        }
+                    }
+                }
+            });
+        }
+        ret
+    }
+
+    fn count(&self, counter: &AtomicU64) {
+        if Config::EnableStats::VALUE {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn io(&self) -> &Config::IO {
+        self.io.as_ref().unwrap()
+    }
+
+    fn len(&self) -> u64 {
+        self.len.load(Ordering::Acquire)
+    }
+
+    // Write back errors have been passed to the observer's `on_writeback` by the time `flush`
+    // returns them; the source is handed back either way, without the blocks that failed.
+    pub fn into_inner(mut self) -> Config::Source {
+        let _ = self.flush();
+        self.io.take().unwrap().into_inner()
+    }
+
+    pub fn read_chunks<R: RangeBounds<u64>, F: FnMut(&[u8])>(&self, range: R, f: F) {
+        let (start, end) = self.bounds(&range);
+        self.chunks(start, end, f);
+    }
+
+    pub fn read<R: RangeBounds<u64>>(&self, range: R, buf: &mut [u8]) -> usize {
+        let (start, end) = self.bounds(&range);
+        let end = std::cmp::min(end, start.saturating_add(buf.len() as u64));
+        let mut copied = 0;
+        self.chunks(start, end, |chunk| {
+            buf[copied..(copied + chunk.len())].copy_from_slice(chunk);
+            copied += chunk.len();
+        });
+        copied
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        let mut ret = Ok(());
+        for idx in 0..self.sets.count() {
+            self.sets.set_at(idx).write(|set| {
+                for frame in 0..set.count() {
+                    let meta = set.meta(frame);
+                    if meta.page != NIL && meta.dirty {
+                        let res = self.write_back(set, frame);
+                        if ret.is_ok() {
+                            ret = res;
+                        }
+                    }
+                }
+            });
+        }
+        ret
+    }
+
+    // Drops every cached block overlapping `range`, writing dirty ones back first. Returns the
+    // first write back error, leaving that block cached.
+    pub fn invalidate<R: RangeBounds<u64>>(&self, range: R) -> std::io::Result<()> {
+        let start = match range.start_bound() {
+            Bound::Included(s) => *s,
+            Bound::Excluded(s) => s.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => e.saturating_add(1),
+            Bound::Excluded(e) => *e,
+            Bound::Unbounded => std::cmp::max(self.len(), start),
+        };
+        if start >= end {
+            return Ok(());
+        }
+        let (first, last) = (start / Self::BLOCK_SIZE, (end - 1) / Self::BLOCK_SIZE);
+        let mut ret = Ok(());
+        let mut keep_first = |res: std::io::Result<()>| {
+            if ret.is_ok() {
+                ret = res;
+            }
+        };
+        // Short ranges look their pages up, longer ones walk the cached frames instead of every
+        // page number in the range.
+        if last - first < self.sets.count() as u64 {
+            for page in first..=last {
+                self.sets.set(page).write(|set| {
+                    let frame = set.lookup().find(page);
+                    if frame != NULL && set.meta(frame).page == page {
+                        keep_first(self.drop_frame(set, frame));
+                    }
+                });
+            }
+        } else {
+            for idx in 0..self.sets.count() {
+                self.sets.set_at(idx).write(|set| {
+                    for frame in 0..set.count() {
+                        let page = set.meta(frame).page;
+                        if page != NIL && page >= first && page <= last {
+                            keep_first(self.drop_frame(set, frame));
+                        }
+                    }
+                });
+            }
+        }
+        ret
     }
 
-    pub fn write(&mut self, _offset: u64, _buf: &[u8]) -> usize {
-        unimplemented!()
+    // Empties `frame`, writing it back first if it's dirty. Keeps it cached if that fails.
+    fn drop_frame(&self, set: &mut SetOf<Config>, frame: usize) -> std::io::Result<()> {
+        if set.meta(frame).dirty {
+            self.write_back(set, frame)?;
+        }
+        let page = set.meta(frame).page;
+        set.lookup_mut().remove(page, frame);
+        *set.meta_mut(frame) = BlockMeta::default();
+        set.replace_mut().record_remove(frame);
+        Ok(())
+    }
+
+    fn bounds<R: RangeBounds<u64>>(&self, range: &R) -> (u64, u64) {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(s) => *s,
+            Bound::Excluded(s) => s.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => e.saturating_add(1),
+            Bound::Excluded(e) => *e,
+            Bound::Unbounded => len,
+        };
+        let end = std::cmp::min(end, len);
+        (std::cmp::min(start, end), end)
+    }
+
+    fn chunks<F: FnMut(&[u8])>(&self, start: u64, end: u64, mut f: F) {
+        let mut pos = start;
+        let mut scratch = Vec::new();
+        while pos < end {
+            let page = pos / Self::BLOCK_SIZE;
+            let offset = (pos % Self::BLOCK_SIZE) as usize;
+            let count = std::cmp::min(Self::BLOCK_SIZE - offset as u64, end - pos) as usize;
+            let res = self.sets.set(page).write(|set| {
+                let frame = self.frame(set, page, true, true)?;
+                if frame == NULL {
+                    scratch.resize(Self::BLOCK_SIZE as usize, 0);
+                    self.fetch(page, &mut scratch)?;
+                    f(&scratch[offset..(offset + count)]);
+                } else {
+                    f(&set.block(frame)[offset..(offset + count)]);
+                }
+                Ok::<(), std::io::Error>(())
+            });
+            if res.is_err() {
+                return;
+            }
+            pos += count as u64;
+        }
+    }
+
+    // Returns the frame holding `page`, evicting a victim and fetching it from the source on a
+    // miss. Without `fill` the frame is zeroed instead, for blocks that are about to be
+    // overwritten entirely. With `admit` the replacement policy may decline caching a missed
+    // page, in which case NULL is returned and the victim is left alone.
+    fn frame(
+        &self,
+        set: &mut SetOf<Config>,
+        page: u64,
+        fill: bool,
+        admit: bool,
+    ) -> std::io::Result<usize> {
+        let frame = set.lookup().find(page);
+        if frame != NULL && set.meta(frame).page == page {
+            set.replace_mut().record_access(frame);
+            self.count(&self.counters.hits);
+            self.observer.on_hit(page);
+            return Ok(frame);
+        }
+
+        self.count(&self.counters.misses);
+        self.observer.on_miss(page);
+        let frame = set.replace_mut().replace(page);
+        if admit && !set.replace_mut().admit(page, frame) {
+            self.count(&self.counters.admission_rejects);
+            return Ok(NULL);
+        }
+        if let Err(e) = self.evict(set, frame) {
+            // The victim stays cached, dirty, though `replace` may have dropped it already.
+            let victim = set.meta(frame).page;
+            set.replace_mut().record_remove(frame);
+            set.replace_mut().record_insert(frame, victim);
+            set.replace_mut().record_dirty(frame, true);
+            return Err(e);
+        }
+        let mut latency = None;
+        if fill {
+            let start = ReplaceOf::<Config>::COST_AWARE.then(Instant::now);
+            if let Err(e) = self.fetch(page, set.block_mut(frame)) {
+                set.replace_mut().record_remove(frame);
+                return Err(e);
+            }
+            latency = start.map(|start| start.elapsed());
+        } else {
+            for b in set.block_mut(frame).iter_mut() {
+                *b = 0;
+            }
+        }
+        set.meta_mut(frame).page = page;
+        set.lookup_mut().insert(page, frame);
+        set.replace_mut().record_insert(frame, page);
+        if let Some(latency) = latency {
+            let cost = self.io().miss_cost(page, latency);
+            set.replace_mut().record_cost(frame, cost);
+        }
+        Ok(frame)
+    }
+
+    fn fetch(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
+        let res = self.io().read(page, block);
+        if let Err(e) = &res {
+            self.count(&self.counters.source_errors);
+            self.observer.on_source_error(page, e);
+        }
+        res
+    }
+
+    fn evict(&self, set: &mut SetOf<Config>, frame: usize) -> std::io::Result<()> {
+        let BlockMeta { page, dirty } = *set.meta(frame);
+        if page == NIL {
+            return Ok(());
+        }
+        if dirty {
+            self.write_back(set, frame)?;
+        }
+        self.count(&self.counters.evictions);
+        self.observer.on_evict(page, dirty);
+        set.lookup_mut().remove(page, frame);
+        *set.meta_mut(frame) = BlockMeta::default();
+        Ok(())
+    }
+
+    fn write_back(&self, set: &mut SetOf<Config>, frame: usize) -> std::io::Result<()> {
+        let page = set.meta(frame).page;
+        let extent = self.len().saturating_sub(page * Self::BLOCK_SIZE);
+        let block = set.block(frame);
+        let count = std::cmp::min(extent, block.len() as u64) as usize;
+        let res = self.io().write_back(page, &block[..count]);
+        self.observer.on_writeback(page, &res);
+        if res.is_ok() {
+            self.count(&self.counters.writebacks);
+            set.meta_mut(frame).dirty = false;
+            set.replace_mut().record_dirty(frame, false);
+        } else {
+            self.count(&self.counters.source_errors);
+        }
+        res
+    }
+}
+
+// Dirty blocks are written back when the cache is dropped, like `BufWriter` does; errors only reach
+// the observer. Nothing is written while unwinding, since a set may still be borrowed.
+impl<Config: CacheConfig> Drop for CacheImpl<Config> {
+    fn drop(&mut self) {
+        if self.io.is_some() && !std::thread::panicking() {
+            let _ = self.flush();
+        }
+    }
+}
+
+impl<Config: CacheConfig> CacheImpl<Config>
+where
+    Config::Source: Write,
+    Config::IO: Writer<Config::Source>,
+{
+    pub fn write(&self, offset: u64, buf: &[u8]) -> usize {
+        // Bytes past u64::MAX can't be addressed, so such a write stops short of them.
+        let end = offset.saturating_add(buf.len() as u64);
+        let buf = &buf[..((end - offset) as usize)];
+        let mut written = 0;
+        while written < buf.len() {
+            let pos = offset + written as u64;
+            let page = pos / Self::BLOCK_SIZE;
+            let block_offset = (pos % Self::BLOCK_SIZE) as usize;
+            let count = std::cmp::min(
+                Self::BLOCK_SIZE as usize - block_offset,
+                buf.len() - written,
+            );
+            let data = &buf[written..(written + count)];
+            let fill = count < Self::BLOCK_SIZE as usize && page * Self::BLOCK_SIZE < self.len();
+            let res = self.sets.set(page).write(|set| {
+                let frame = self.frame(set, page, fill, false)?;
+                set.block_mut(frame)[block_offset..(block_offset + count)].copy_from_slice(data);
+                // Only once the data is cached, so a failed miss doesn't extend the source.
+                self.len.fetch_max(pos + count as u64, Ordering::AcqRel);
+                set.meta_mut(frame).dirty = true;
+                set.replace_mut().record_dirty(frame, true);
+                if Config::WriteThrough::VALUE {
+                    self.write_back(set, frame)?;
+                }
+                Ok::<(), std::io::Error>(())
+            });
+            if res.is_err() {
+                return written;
+            }
+            written += count;
+        }
+        written
     }
 }
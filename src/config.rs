@@ -4,12 +4,14 @@
 // write_strategy: { WriteBack, WriteThrough }
 // async_write: bool
 // associativity: { DirectMapped, FullyAssociative, NWay(usize) }
-// replacement: { Random, LRU, LFU, LRFU, FIFO }
+// replacement: { Random, LRU, CFLRU, LFU, LRFU, FIFO, Clock, ARC, 2Q, SLRU, LIRS, S3FIFO, TreePLRU, BitPLRU, SRRIP, BRRIP, DRRIP, Duel<A, B>, GreedyDual }
+// way_prediction: bool
 // blocks_per_fetch: usize
 // thread_safe: bool
 // enable_stats: bool
 
 use super::detail::*;
+use super::observer::CacheObserver;
 
 use std::io::{Read, Seek};
 
@@ -25,6 +27,8 @@ pub trait CacheConfig {
     type ThreadSafe: Bool;
     type EnableStats: Bool;
     type WrappedSource: InnerMut<Self::Source>;
+    type IO: Reader<Self::Source>;
+    type Observer: CacheObserver;
     type S: Sets;
 }
 
@@ -178,4 +182,4 @@ macro_rules! cache_config {
         }
     };
 }
-*/
\ No newline at end of file
+*/
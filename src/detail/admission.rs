@@ -0,0 +1,357 @@
+use super::*;
+
+const NIL: u64 = u64::MAX;
+
+const ROWS: usize = 4;
+const MAX_COUNT: u8 = 15;
+const COUNTERS_PER_ITEM: usize = 4;
+const MIN_WIDTH: usize = 64;
+// Counters are halved every `SAMPLE_FACTOR * width` recorded accesses.
+const SAMPLE_FACTOR: usize = 10;
+
+// Count-min sketch of access frequencies with a doorkeeper bloom filter in front, so pages seen
+// only once never reach the counters. Periodically halving the counters and clearing the
+// doorkeeper ages out pages that used to be popular.
+pub struct FrequencySketch {
+    counters: Vec<u8>,
+    doorkeeper: Vec<u64>,
+    mask: usize,
+    additions: usize,
+    sample: usize,
+}
+
+impl FrequencySketch {
+    pub const MEM_PER_ITEM: usize = ROWS * COUNTERS_PER_ITEM + COUNTERS_PER_ITEM / 8;
+
+    pub fn new(items: usize) -> Self {
+        let width = std::cmp::max((items * COUNTERS_PER_ITEM).next_power_of_two(), MIN_WIDTH);
+        Self {
+            counters: vec![0; ROWS * width],
+            doorkeeper: vec![0; width / 64],
+            mask: width - 1,
+            additions: 0,
+            sample: width * SAMPLE_FACTOR,
+        }
+    }
+
+    fn index(&self, hash: u64, row: usize) -> usize {
+        let step = (hash >> 32) as usize | 1;
+        let col = (hash as usize).wrapping_add(row.wrapping_mul(step)) & self.mask;
+        row * (self.mask + 1) + col
+    }
+
+    fn doorkeeper_bits(&self, hash: u64) -> [usize; 2] {
+        [
+            hash.rotate_left(16) as usize & self.mask,
+            hash.rotate_left(40) as usize & self.mask,
+        ]
+    }
+
+    fn in_doorkeeper(&self, hash: u64) -> bool {
+        self.doorkeeper_bits(hash)
+            .iter()
+            .all(|bit| self.doorkeeper[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn increment(&mut self, page: u64) {
+        let hash = hash64(page);
+        if self.in_doorkeeper(hash) {
+            for row in 0..ROWS {
+                let idx = self.index(hash, row);
+                self.counters[idx] = std::cmp::min(self.counters[idx] + 1, MAX_COUNT);
+            }
+        } else {
+            for bit in self.doorkeeper_bits(hash).iter() {
+                self.doorkeeper[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample {
+            self.age();
+        }
+    }
+
+    pub fn estimate(&self, page: u64) -> u8 {
+        let hash = hash64(page);
+        let count = (0..ROWS)
+            .map(|row| self.counters[self.index(hash, row)])
+            .min()
+            .unwrap_or(0);
+        count + self.in_doorkeeper(hash) as u8
+    }
+
+    pub fn age(&mut self) {
+        for c in self.counters.iter_mut() {
+            *c /= 2;
+        }
+        for bits in self.doorkeeper.iter_mut() {
+            *bits = 0;
+        }
+        self.additions /= 2;
+    }
+}
+
+// TinyLFU admission in front of any replacement policy: a missed page only takes the frame of
+// the victim chosen by `R` if it has been accessed more often recently. Declined pages are served
+// without being cached, and the victim is handed back to `R` as if it had just been inserted,
+// with its dirty flag and miss cost. With `LRU` this is W-TinyLFU with the whole set as the window.
+pub struct TinyLFU<R: Replace> {
+    inner: R,
+    sketch: FrequencySketch,
+    pages: Vec<u64>,
+    dirty: Vec<bool>,
+    // NIL until `record_cost` is called for the frame.
+    costs: Vec<u64>,
+    evicted: u64,
+}
+
+impl<R: Replace> Replace for TinyLFU<R> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - std::mem::size_of::<R>() + R::STATIC_META_MEM;
+    const META_MEM_PER_BLOCK: usize = R::META_MEM_PER_BLOCK
+        + 2 * std::mem::size_of::<u64>()
+        + std::mem::size_of::<bool>()
+        + FrequencySketch::MEM_PER_ITEM;
+    const COST_AWARE: bool = R::COST_AWARE;
+
+    fn new(count: usize) -> Self {
+        Self {
+            inner: R::new(count),
+            sketch: FrequencySketch::new(count),
+            pages: vec![NIL; count],
+            dirty: vec![false; count],
+            costs: vec![NIL; count],
+            evicted: NIL,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.sketch.increment(page);
+        let frame = self.inner.replace(page);
+        self.evicted = self.pages[frame];
+        frame
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.sketch.increment(self.pages[idx]);
+        self.inner.record_access(idx);
+    }
+
+    fn admit(&mut self, page: u64, victim: usize) -> bool {
+        if self.evicted == NIL || self.sketch.estimate(page) > self.sketch.estimate(self.evicted) {
+            return true;
+        }
+        // `replace` may have moved the victim to a ghost list or given its frame to `page`.
+        self.inner.record_remove(victim);
+        self.inner.record_insert(victim, self.evicted);
+        // `record_insert` sets the frame up clean and at the default cost, like a fetched page.
+        if self.dirty[victim] {
+            self.inner.record_dirty(victim, true);
+        }
+        if self.costs[victim] != NIL {
+            self.inner.record_cost(victim, self.costs[victim]);
+        }
+        false
+    }
+
+    fn join_set(&mut self, set: usize, sets: usize) {
+        self.inner.join_set(set, sets);
+    }
+
+    fn join_duel(&mut self, set: usize, sets: usize, duel: &std::sync::Arc<SetDuel>) {
+        self.inner.join_duel(set, sets, duel);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        self.pages[idx] = page;
+        self.dirty[idx] = false;
+        self.costs[idx] = NIL;
+        self.inner.record_insert(idx, page);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.pages[idx] = NIL;
+        self.dirty[idx] = false;
+        self.costs[idx] = NIL;
+        self.inner.record_remove(idx);
+    }
+
+    fn record_dirty(&mut self, idx: usize, dirty: bool) {
+        self.dirty[idx] = dirty;
+        self.inner.record_dirty(idx, dirty);
+    }
+
+    fn record_cost(&mut self, idx: usize, cost: u64) {
+        self.costs[idx] = cost;
+        self.inner.record_cost(idx, cost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sketch() {
+        let mut s = FrequencySketch::new(16);
+        s.increment(1);
+        assert_eq!(s.estimate(1), 1);
+        for _ in 0..5 {
+            s.increment(1);
+        }
+        assert_eq!(s.estimate(1), 6);
+        assert_eq!(s.estimate(2), 0);
+        s.age();
+        assert_eq!(s.estimate(1), 2);
+    }
+
+    #[test]
+    fn admission() {
+        let mut r = TinyLFU::<FALRU>::new(2);
+        assert_eq!(r.replace(0), 0);
+        assert!(r.admit(0, 0));
+        r.record_insert(0, 0);
+        assert_eq!(r.replace(1), 1);
+        assert!(r.admit(1, 1));
+        r.record_insert(1, 1);
+        for _ in 0..3 {
+            r.record_access(0);
+            r.record_access(1);
+        }
+        // A page seen once doesn't displace popular ones.
+        let frame = r.replace(2);
+        assert!(!r.admit(2, frame));
+        assert_eq!(r.pages, vec![0, 1]);
+        for _ in 0..8 {
+            let frame = r.replace(2);
+            if r.admit(2, frame) {
+                return;
+            }
+        }
+        panic!("page 2 never admitted");
+    }
+
+    // Remembers the page `replace` picked every frame for, like the list based policies do.
+    struct Claims {
+        pages: Vec<u64>,
+        next: usize,
+    }
+
+    impl Replace for Claims {
+        const STATIC_META_MEM: usize = 0;
+        const META_MEM_PER_BLOCK: usize = 0;
+
+        fn new(count: usize) -> Self {
+            Self {
+                pages: vec![NIL; count],
+                next: 0,
+            }
+        }
+
+        fn replace(&mut self, page: u64) -> usize {
+            let frame = self.next;
+            self.next = (self.next + 1) % self.pages.len();
+            self.pages[frame] = page;
+            frame
+        }
+
+        fn record_access(&mut self, _: usize) {}
+
+        fn record_insert(&mut self, idx: usize, page: u64) {
+            self.pages[idx] = page;
+        }
+
+        fn record_remove(&mut self, idx: usize) {
+            self.pages[idx] = NIL;
+        }
+    }
+
+    #[test]
+    fn rejection_restores_victim() {
+        let mut r = TinyLFU::<Claims>::new(2);
+        for page in 0..2 {
+            let frame = r.replace(page);
+            r.record_insert(frame, page);
+        }
+        for _ in 0..3 {
+            r.record_access(0);
+            r.record_access(1);
+        }
+        let frame = r.replace(2);
+        assert!(!r.admit(2, frame));
+        assert_eq!(r.inner.pages, vec![0, 1]);
+
+        // ARC moves the victim to a ghost list on `replace`; it must stay resident.
+        let mut r = TinyLFU::<FAARC>::new(4);
+        for page in 0..4 {
+            let frame = r.replace(page);
+            r.record_insert(frame, page);
+            for _ in 0..3 {
+                r.record_access(frame);
+            }
+        }
+        for page in 4..64 {
+            let frame = r.replace(page);
+            if r.admit(page, frame) {
+                r.record_insert(frame, page);
+            }
+        }
+        let mut pages = r.pages.clone();
+        pages.sort_unstable();
+        assert_eq!(pages, vec![0, 1, 2, 3]);
+        for page in 64..128 {
+            let frame = r.replace(page % 8);
+            if r.admit(page % 8, frame) {
+                r.record_insert(frame, page % 8);
+            }
+        }
+    }
+
+    struct Whole;
+
+    impl ConstF32 for Whole {
+        const VALUE: f32 = 1.0;
+    }
+
+    // Caches pages 0 to 3 and makes them too popular to be displaced. Returns their frames.
+    fn popular<R: Replace>(r: &mut TinyLFU<R>) -> Vec<usize> {
+        let frames: Vec<usize> = (0..4)
+            .map(|page| {
+                let frame = r.replace(page);
+                r.record_insert(frame, page);
+                frame
+            })
+            .collect();
+        for _ in 0..3 {
+            for &frame in frames.iter() {
+                r.record_access(frame);
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn rejection_keeps_dirty_and_cost() {
+        // All blocks are dirty, so CFLRU falls back to the LRU one. Once it is handed back it must
+        // still be dirty, or it would be picked again as the only clean one.
+        let mut r = TinyLFU::<CFLRU<Arr<LRUMeta, 4>, Arr<bool, 4>, Whole>>::new(4);
+        let frames = popular(&mut r);
+        for &frame in frames.iter() {
+            r.record_dirty(frame, true);
+        }
+        assert_eq!(r.replace(9), frames[0]);
+        assert!(!r.admit(9, frames[0]));
+        assert_eq!(r.replace(10), frames[1]);
+
+        // Page 0 is cheapest, but still worth more than the others once its cost is restored on
+        // top of the inflation.
+        let mut r = TinyLFU::<GreedyDual<Arr<HeapElem<GDMeta>, 4>, Arr<usize, 4>>>::new(4);
+        let frames = popular(&mut r);
+        for (page, &frame) in frames.iter().enumerate() {
+            r.record_cost(frame, if page == 0 { 100 } else { 150 });
+        }
+        assert_eq!(r.replace(9), frames[0]);
+        assert!(!r.admit(9, frames[0]));
+        assert_ne!(r.replace(10), frames[0]);
+    }
+}
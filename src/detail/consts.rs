@@ -27,3 +27,43 @@ pub trait Array<T: Sized + Default + Clone>: Clone + Default {
     fn get_ref(&self) -> &[T];
     fn get_mut(&mut self) -> &mut [T];
 }
+
+#[cfg(test)]
+#[derive(Clone)]
+pub struct Arr<T, const N: usize>([T; N]);
+
+#[cfg(test)]
+impl<T: Default, const N: usize> Default for Arr<T, N> {
+    fn default() -> Self {
+        Self(std::array::from_fn(|_| T::default()))
+    }
+}
+
+#[cfg(test)]
+impl<T: Sized + Default + Clone, const N: usize> Array<T> for Arr<T, N> {
+    const LEN: usize = N;
+
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn new_with(val: T) -> Self {
+        Self(std::array::from_fn(|_| val.clone()))
+    }
+
+    fn get_ref(&self) -> &[T] {
+        &self.0
+    }
+
+    fn get_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+pub struct Usize<const N: usize>;
+
+#[cfg(test)]
+impl<const N: usize> ConstUsize for Usize<N> {
+    const VALUE: usize = N;
+}
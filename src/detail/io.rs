@@ -1,21 +1,48 @@
 use super::*;
 
-use std::io::{Read, Write, Seek, SeekFrom};
 use std::cell::RefCell;
-use std::sync::{RwLock, Mutex, Condvar, Arc, atomic::AtomicBool, atomic::Ordering};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread::JoinHandle;
+use std::time::Instant;
 
-const NIL: u64 = std::u64::MAX;
+const NULL: usize = usize::MAX;
+const NIL: u64 = u64::MAX;
 
-pub trait Reader<Source: Read + Seek> where Self: std::marker::Sized {
+pub trait Reader<Source: Read + Seek>
+where
+    Self: std::marker::Sized,
+{
     fn new(source: Source) -> std::io::Result<Self>;
     fn into_inner(self) -> Source;
     fn len(&self) -> u64;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn read(&self, page: u64, block: &mut [u8]) -> std::io::Result<()>;
+    fn latency(&self) -> Option<&IOLatency> {
+        None
+    }
+    fn queue_depth(&self) -> usize {
+        0
+    }
+    // What missing `page` costs, given that reading it just took `latency`, for cost aware
+    // replacement. Override to weigh pages by something else, e.g. the tier they live on.
+    fn miss_cost(&self, _page: u64, latency: std::time::Duration) -> u64 {
+        std::cmp::min(latency.as_nanos(), u64::MAX as u128) as u64
+    }
+    // Writes a dirty block back to the source. Needs no `Writer` bound, so evictions on the read
+    // path and flushes can reach it; IOs that are a `Writer` override it with `write`.
+    fn write_back(&self, _page: u64, _block: &[u8]) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "io_cache source is not writable",
+        ))
+    }
 }
 
 pub trait Writer<Source: Read + Write + Seek>: Reader<Source> {
-    fn write(&mut self, page: u64, block: &[u8]) -> std::io::Result<()>;
+    fn write(&self, page: u64, block: &[u8]) -> std::io::Result<()>;
 }
 
 struct SrcInfo<Source: Read + Seek> {
@@ -23,28 +50,82 @@ struct SrcInfo<Source: Read + Seek> {
     len: u64,
 }
 
+fn read_block<Source: Read>(source: &mut Source, block: &mut [u8]) -> std::io::Result<()> {
+    let mut pos = 0;
+    while pos < block.len() {
+        match source.read(&mut block[pos..]) {
+            Ok(0) => break,
+            Ok(n) => pos += n,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    for b in block[pos..].iter_mut() {
+        *b = 0;
+    }
+    Ok(())
+}
+
+fn timed<Ret, F: FnOnce() -> Ret>(hist: Option<&LatencyHistogram>, f: F) -> Ret {
+    match hist {
+        Some(hist) => {
+            let start = Instant::now();
+            let ret = f();
+            hist.record(start.elapsed());
+            ret
+        }
+        None => f(),
+    }
+}
+
+fn new_latency<Timed: Bool>() -> Option<IOLatency> {
+    if Timed::VALUE {
+        Some(IOLatency::new())
+    } else {
+        None
+    }
+}
+
 impl<Source: Read + Seek> SrcInfo<Source> {
     fn new(mut source: Source) -> std::io::Result<Self> {
         let len = source.seek(SeekFrom::End(0))?;
-        Ok(Self {
-            source,
-            len,
-        })
+        Ok(Self { source, len })
     }
 }
 
-pub struct SyncIO<Source: Read + Seek, BlockSz: ConstUsize> {
+pub struct SyncIO<Source: Read + Seek, BlockSz: ConstUsize, Timed: Bool> {
     source: RefCell<SrcInfo<Source>>,
-    _marker: std::marker::PhantomData<BlockSz>,
+    latency: Option<IOLatency>,
+    _marker1: std::marker::PhantomData<BlockSz>,
+    _marker2: std::marker::PhantomData<Timed>,
 }
 
-impl<Source: Read + Seek, BlockSz: ConstUsize> Reader<Source> for SyncIO<Source, BlockSz> {
-    fn new(source: Source) -> std::io::Result<Self> {
+impl<Source: Read + Seek, BlockSz: ConstUsize, Timed: Bool> SyncIO<Source, BlockSz, Timed> {
+    fn open(source: Source) -> std::io::Result<Self> {
         Ok(Self {
             source: RefCell::new(SrcInfo::new(source)?),
-            _marker: std::marker::PhantomData,
+            latency: new_latency::<Timed>(),
+            _marker1: std::marker::PhantomData,
+            _marker2: std::marker::PhantomData,
+        })
+    }
+
+    fn read_page(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
+        let mut s = self.source.borrow_mut();
+        timed(self.latency.as_ref().map(|l| &l.read), || {
+            s.source
+                .seek(SeekFrom::Start(page * BlockSz::VALUE as u64))?;
+            read_block(&mut s.source, block)
         })
     }
+}
+
+impl<Source: Read + Write + Seek, BlockSz: ConstUsize, Timed: Bool> Reader<Source>
+    for SyncIO<Source, BlockSz, Timed>
+{
+    fn new(source: Source) -> std::io::Result<Self> {
+        Self::open(source)
+    }
 
     fn into_inner(self) -> Source {
         self.source.into_inner().source
@@ -55,18 +136,31 @@ impl<Source: Read + Seek, BlockSz: ConstUsize> Reader<Source> for SyncIO<Source,
     }
 
     fn read(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
-        let mut s = self.source.borrow_mut();
-        s.source.seek(SeekFrom::Start(page * BlockSz::VALUE as u64))?;
-        s.source.read(block)?;
-        Ok(())
+        self.read_page(page, block)
+    }
+
+    fn latency(&self) -> Option<&IOLatency> {
+        self.latency.as_ref()
+    }
+
+    fn write_back(&self, page: u64, block: &[u8]) -> std::io::Result<()> {
+        self.write(page, block)
     }
 }
 
-impl<Source: Read + Write + Seek, BlockSz: ConstUsize> Writer<Source> for SyncIO<Source, BlockSz> {
-    fn write(&mut self, page: u64, block: &[u8]) -> std::io::Result<()> {
-        let mut s = self.source.get_mut();
-        let pos = s.source.seek(SeekFrom::Start(page * BlockSz::VALUE as u64))?;
-        let pos = pos + s.source.write(block)? as u64;
+impl<Source: Read + Write + Seek, BlockSz: ConstUsize, Timed: Bool> Writer<Source>
+    for SyncIO<Source, BlockSz, Timed>
+{
+    fn write(&self, page: u64, block: &[u8]) -> std::io::Result<()> {
+        let mut s = self.source.borrow_mut();
+        let pos = timed(self.latency.as_ref().map(|l| &l.write), || {
+            let pos = s
+                .source
+                .seek(SeekFrom::Start(page * BlockSz::VALUE as u64))?;
+            s.source.write_all(block)?;
+            Ok::<u64, std::io::Error>(pos)
+        })?;
+        let pos = pos + block.len() as u64;
         let len = s.len;
         if len < pos {
             s.len = pos;
@@ -75,147 +169,618 @@ impl<Source: Read + Write + Seek, BlockSz: ConstUsize> Writer<Source> for SyncIO
     }
 }
 
-struct AsyncIOMeta<Block: Array<u8> + Send + Sync, Queue: Array<(u64, Block)> + Send + Sync, Table: Array<(u64, usize)> + Send + Sync> {
+// `SyncIO` for sources that can't be written, e.g. `Cursor<&[u8]>`. Writing back a block fails.
+pub struct ReadOnlyIO<Source: Read + Seek, BlockSz: ConstUsize, Timed: Bool>(
+    SyncIO<Source, BlockSz, Timed>,
+);
+
+impl<Source: Read + Seek, BlockSz: ConstUsize, Timed: Bool> Reader<Source>
+    for ReadOnlyIO<Source, BlockSz, Timed>
+{
+    fn new(source: Source) -> std::io::Result<Self> {
+        Ok(Self(SyncIO::open(source)?))
+    }
+
+    fn into_inner(self) -> Source {
+        self.0.source.into_inner().source
+    }
+
+    fn len(&self) -> u64 {
+        self.0.source.borrow().len
+    }
+
+    fn read(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
+        self.0.read_page(page, block)
+    }
+
+    fn latency(&self) -> Option<&IOLatency> {
+        self.0.latency.as_ref()
+    }
+}
+
+// Pending writes are kept in a ring buffer, `queue[front..back]`, and indexed by page in `table`,
+// an open addressing hash table with linear probing. Writes to a page that is still queued
+// overwrite the queued block in place, and reads of a queued page are served from the queue. The
+// front entry stays queued until it has been written, and is written again if it was overwritten
+// meanwhile. A failed write stops the worker until the error has been returned to a caller.
+struct AsyncIOMeta<
+    Block: Array<u8> + Send + Sync,
+    Queue: Array<(u64, Block)> + Send + Sync,
+    Table: Array<(u64, usize)> + Send + Sync,
+> {
     queue: Queue,
     table: Table,
+    enqueued: Vec<Option<Instant>>,
     front: usize,
     back: usize,
+    len: u64,
     end: bool,
+    rewritten: bool,
+    error: Option<std::io::Error>,
     _marker: std::marker::PhantomData<Block>,
 }
 
-impl<Block: Array<u8> + Send + Sync, Queue: Array<(u64, Block)> + Send + Sync, Table: Array<(u64, usize)> + Send + Sync> AsyncIOMeta<Block, Queue, Table> {
-    fn new() -> Self {
+impl<
+        Block: Array<u8> + Send + Sync,
+        Queue: Array<(u64, Block)> + Send + Sync,
+        Table: Array<(u64, usize)> + Send + Sync,
+    > AsyncIOMeta<Block, Queue, Table>
+{
+    fn new(len: u64, timed: bool) -> Self {
+        assert!(
+            Table::LEN >= Queue::LEN,
+            "io_cache async write table must be at least as large as the queue"
+        );
         Self {
             queue: Queue::new(),
             table: Table::new_with((NIL, 0)),
+            enqueued: vec![None; if timed { Queue::LEN } else { 0 }],
             front: 0,
             back: 0,
+            len,
             end: false,
+            rewritten: false,
+            error: None,
             _marker: std::marker::PhantomData,
         }
     }
+
+    fn depth(&self) -> usize {
+        (self.back + Queue::LEN - self.front) % Queue::LEN
+    }
+
+    fn is_full(&self) -> bool {
+        (self.back + 1) % Queue::LEN == self.front
+    }
+
+    fn home(page: u64) -> usize {
+        (hash64(page) % Table::LEN as u64) as usize
+    }
+
+    fn slot(&self, page: u64) -> usize {
+        let table = self.table.get_ref();
+        let mut idx = Self::home(page);
+        for _ in 0..Table::LEN {
+            if table[idx].0 == NIL {
+                return NULL;
+            }
+            if table[idx].0 == page {
+                return idx;
+            }
+            idx = (idx + 1) % Table::LEN;
+        }
+        NULL
+    }
+
+    fn find(&self, page: u64) -> usize {
+        match self.slot(page) {
+            NULL => NULL,
+            slot => self.table.get_ref()[slot].1,
+        }
+    }
+
+    fn push(&mut self, page: u64, block: &[u8]) {
+        let back = self.back;
+        {
+            let entry = &mut self.queue.get_mut()[back];
+            entry.0 = page;
+            entry.1.get_mut()[..block.len()].copy_from_slice(block);
+            for b in entry.1.get_mut()[block.len()..].iter_mut() {
+                *b = 0;
+            }
+        }
+        if !self.enqueued.is_empty() {
+            self.enqueued[back] = Some(Instant::now());
+        }
+        let table = self.table.get_mut();
+        let mut idx = Self::home(page);
+        while table[idx].0 != NIL {
+            idx = (idx + 1) % Table::LEN;
+        }
+        table[idx] = (page, back);
+        self.back = (back + 1) % Queue::LEN;
+    }
+
+    fn peek(&mut self, block: &mut Block) -> u64 {
+        self.rewritten = false;
+        let entry = &self.queue.get_ref()[self.front];
+        block.get_mut().copy_from_slice(entry.1.get_ref());
+        entry.0
+    }
+
+    fn pop(&mut self) -> Option<Instant> {
+        let front = self.front;
+        let page = self.queue.get_ref()[front].0;
+        let enqueued = match self.enqueued.get_mut(front) {
+            Some(t) => t.take(),
+            None => None,
+        };
+        self.remove(page);
+        self.front = (front + 1) % Queue::LEN;
+        enqueued
+    }
+
+    // Backward shift deletion, so no tombstones are left behind to lengthen later probes.
+    fn remove(&mut self, page: u64) {
+        let mut hole = self.slot(page);
+        if hole == NULL {
+            return;
+        }
+        let table = self.table.get_mut();
+        let mut idx = hole;
+        loop {
+            idx = (idx + 1) % Table::LEN;
+            if table[idx].0 == NIL {
+                break;
+            }
+            let home = Self::home(table[idx].0);
+            let movable = if hole <= idx {
+                home <= hole || home > idx
+            } else {
+                home <= hole && home > idx
+            };
+            if movable {
+                table[hole] = table[idx];
+                hole = idx;
+            }
+        }
+        table[hole].0 = NIL;
+    }
 }
 
-pub struct AsyncIO<Source: Read + Write + Seek + Send + Sync, Block: Array<u8> + Send + Sync, Queue: Array<(u64, Block)> + Send + Sync, Table: Array<(u64, usize)> + Send + Sync> {
+pub struct AsyncIO<
+    Source: Read + Write + Seek + Send + Sync,
+    Block: Array<u8> + Send + Sync,
+    Queue: Array<(u64, Block)> + Send + Sync,
+    Table: Array<(u64, usize)> + Send + Sync,
+    Timed: Bool,
+> {
     inner: Arc<AsyncIOImpl<Source, Block, Queue, Table>>,
-    worker: Mutex<Option<JoinHandle<std::io::Result<()>>>>,
+    worker: Option<JoinHandle<()>>,
+    _marker: std::marker::PhantomData<Timed>,
 }
 
-pub struct AsyncIOImpl<Source: Read + Write + Seek + Send + Sync, Block: Array<u8> + Send + Sync, Queue: Array<(u64, Block)> + Send + Sync, Table: Array<(u64, usize)> + Send + Sync> {
+pub struct AsyncIOImpl<
+    Source: Read + Write + Seek + Send + Sync,
+    Block: Array<u8> + Send + Sync,
+    Queue: Array<(u64, Block)> + Send + Sync,
+    Table: Array<(u64, usize)> + Send + Sync,
+> {
     source: RwLock<SrcInfo<Source>>,
     meta: Mutex<AsyncIOMeta<Block, Queue, Table>>,
     condvar: Condvar,
-    error_flag: AtomicBool,
+    latency: Option<IOLatency>,
 }
 
-fn async_io_worker<Source, Block, Queue, Table>(data: Arc<AsyncIOImpl<Source, Block, Queue, Table>>) -> std::io::Result<()>
-    where Source: Read + Write + Seek + Send + Sync, Block: Array<u8> + Send + Sync, Queue: Array<(u64, Block)> + Send + Sync, Table: Array<(u64, usize)> + Send + Sync
+fn async_io_worker<Source, Block, Queue, Table>(data: Arc<AsyncIOImpl<Source, Block, Queue, Table>>)
+where
+    Source: Read + Write + Seek + Send + Sync,
+    Block: Array<u8> + Send + Sync,
+    Queue: Array<(u64, Block)> + Send + Sync,
+    Table: Array<(u64, usize)> + Send + Sync,
 {
     let mut block = Block::new();
-    let mut page: u64 = 0;
     loop {
-        {
+        let (page, count) = {
             let mut lock = data.meta.lock().unwrap();
-            let end = loop {
-                lock = data.condvar.wait(lock).unwrap();
-
+            while lock.front == lock.back || lock.error.is_some() {
                 if lock.end {
-                    break true;
-                }
-
-                if lock.front != lock.back {
-                    page = lock.queue.get_ref()[lock.front].0;
-                    block.get_mut().write(lock.queue.get_ref()[lock.front].1.get_ref())?;
-                    break false;
+                    return;
                 }
-            };
-
-            if end { return Ok(()); }
-        }
+                lock = data.condvar.wait(lock).unwrap();
+            }
+            let page = lock.peek(&mut block);
+            let extent = lock.len.saturating_sub(page * Block::LEN as u64);
+            (page, std::cmp::min(extent, Block::LEN as u64) as usize)
+        };
 
-        {
-            let mut lock = data.source.write().unwrap();
-            lock.source.seek(SeekFrom::Start(page * Block::LEN as u64))?;
-            lock.source.write(block.get_ref())?;
+        let mut source = data.source.write().unwrap();
+        let res = timed(data.latency.as_ref().map(|l| &l.write), || {
+            source
+                .source
+                .seek(SeekFrom::Start(page * Block::LEN as u64))?;
+            source.source.write_all(&block.get_ref()[..count])
+        });
+        if res.is_ok() {
+            let pos = page * Block::LEN as u64 + count as u64;
+            if source.len < pos {
+                source.len = pos;
+            }
         }
-
-        {
-            let mut lock = data.meta.lock().unwrap();
-            lock.front = (lock.front + 1) % Queue::LEN;
-            let mut idx = (hash64(page) % Table::LEN as u64) as usize;
-            {
-                let table = lock.table.get_mut();
-                while table[idx].0 != page {
-                    idx = (idx + 1) % Table::LEN;
+        // Still holding the source lock, so a read of the page either finds it queued or sees it
+        // written.
+        let mut lock = data.meta.lock().unwrap();
+        match res {
+            Ok(()) if !lock.rewritten => {
+                let enqueued = lock.pop();
+                if let (Some(latency), Some(enqueued)) = (data.latency.as_ref(), enqueued) {
+                    latency.queue_wait.record(enqueued.elapsed());
                 }
-                table[idx].0 = NIL;
+            }
+            Ok(()) => {}
+            Err(e) => {
+                let msg = format!("io_cache async write of page {} failed: {}", page, e);
+                lock.error = Some(std::io::Error::new(e.kind(), msg));
             }
         }
+        data.condvar.notify_all();
     }
 }
 
-impl<Source, Block, Queue, Table> AsyncIO<Source, Block, Queue, Table>
-    where Source: Read + Write + Seek + Send + Sync + 'static, Block: Array<u8> + Send + Sync + 'static, Queue: Array<(u64, Block)> + Send + Sync + 'static, Table: Array<(u64, usize)> + Send + Sync + 'static
+impl<Source, Block, Queue, Table, Timed> AsyncIO<Source, Block, Queue, Table, Timed>
+where
+    Source: Read + Write + Seek + Send + Sync + 'static,
+    Block: Array<u8> + Send + Sync + 'static,
+    Queue: Array<(u64, Block)> + Send + Sync + 'static,
+    Table: Array<(u64, usize)> + Send + Sync + 'static,
+    Timed: Bool,
 {
-    fn check_error(&self) -> std::io::Result<()> {
-        if self.inner.error_flag.swap(false, Ordering::SeqCst) {
-            let mut lock = self.worker.lock().unwrap();
-            let ret = std::mem::replace(&mut *lock, None).unwrap().join().unwrap();
-            let t = self.inner.clone();
-            *lock = Some(std::thread::spawn(move || { async_io_worker(t) }));
-            ret
-        } else {
-            Ok(())
+    // Returns a failed write to the caller once, which lets the worker retry it.
+    fn take_error(&self, lock: &mut AsyncIOMeta<Block, Queue, Table>) -> std::io::Result<()> {
+        match lock.error.take() {
+            Some(e) => {
+                self.inner.condvar.notify_all();
+                Err(e)
+            }
+            None => Ok(()),
         }
     }
 }
 
-impl<Source, Block, Queue, Table> Reader<Source> for AsyncIO<Source, Block, Queue, Table>
-    where Source: Read + Write + Seek + Send + Sync + 'static, Block: Array<u8> + Send + Sync + 'static, Queue: Array<(u64, Block)> + Send + Sync + 'static, Table: Array<(u64, usize)> + Send + Sync + 'static
+impl<Source, Block, Queue, Table, Timed> Reader<Source>
+    for AsyncIO<Source, Block, Queue, Table, Timed>
+where
+    Source: Read + Write + Seek + Send + Sync + 'static,
+    Block: Array<u8> + Send + Sync + 'static,
+    Queue: Array<(u64, Block)> + Send + Sync + 'static,
+    Table: Array<(u64, usize)> + Send + Sync + 'static,
+    Timed: Bool,
 {
     fn new(source: Source) -> std::io::Result<Self> {
+        let source = SrcInfo::new(source)?;
+        let len = source.len;
         let inner = Arc::new(AsyncIOImpl {
-            source:RwLock::new(SrcInfo::new(source)?),
-            meta: Mutex::new(AsyncIOMeta::new()),
+            source: RwLock::new(source),
+            meta: Mutex::new(AsyncIOMeta::new(len, Timed::VALUE)),
             condvar: Condvar::new(),
-            error_flag: AtomicBool::new(false),
+            latency: new_latency::<Timed>(),
         });
         let t = inner.clone();
         Ok(Self {
             inner,
-            worker: Mutex::new(Some(std::thread::spawn(move || { async_io_worker(t) }))),
+            worker: Some(std::thread::spawn(move || async_io_worker(t))),
+            _marker: std::marker::PhantomData,
         })
     }
 
     fn into_inner(self) -> Source {
-        self.inner.meta.lock().unwrap().end = true;
-        self.inner.condvar.notify_one();
-        let _ = self.worker.into_inner().unwrap().unwrap().join();
-        match Arc::try_unwrap(self.inner) {
+        // Dropping `self` stops the worker, which leaves `inner` as the last reference.
+        let inner = self.inner.clone();
+        drop(self);
+        match Arc::try_unwrap(inner) {
             Ok(inner) => inner,
             _ => panic!("Failed to unwrap Arc"),
-        }.source.into_inner().unwrap().source
+        }
+        .source
+        .into_inner()
+        .unwrap()
+        .source
     }
 
     fn len(&self) -> u64 {
-        self.inner.source.read().unwrap().len
+        self.inner.meta.lock().unwrap().len
     }
 
     fn read(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
-        self.check_error()?;
+        {
+            let mut lock = self.inner.meta.lock().unwrap();
+            self.take_error(&mut lock)?;
+            let idx = lock.find(page);
+            if idx != NULL {
+                let queued = lock.queue.get_ref()[idx].1.get_ref();
+                block.copy_from_slice(&queued[..block.len()]);
+                return Ok(());
+            }
+        }
         let s = &mut self.inner.source.write().unwrap().source;
-        s.seek(SeekFrom::Start(page * Block::LEN as u64))?;
-        s.read(block)?;
+        timed(self.inner.latency.as_ref().map(|l| &l.read), || {
+            s.seek(SeekFrom::Start(page * Block::LEN as u64))?;
+            read_block(s, block)
+        })
+    }
+
+    fn latency(&self) -> Option<&IOLatency> {
+        self.inner.latency.as_ref()
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.inner.meta.lock().unwrap().depth()
+    }
+
+    fn write_back(&self, page: u64, block: &[u8]) -> std::io::Result<()> {
+        self.write(page, block)
+    }
+}
+
+impl<Source, Block, Queue, Table, Timed> Writer<Source>
+    for AsyncIO<Source, Block, Queue, Table, Timed>
+where
+    Source: Read + Write + Seek + Send + Sync + 'static,
+    Block: Array<u8> + Send + Sync + 'static,
+    Queue: Array<(u64, Block)> + Send + Sync + 'static,
+    Table: Array<(u64, usize)> + Send + Sync + 'static,
+    Timed: Bool,
+{
+    fn write(&self, page: u64, block: &[u8]) -> std::io::Result<()> {
+        let mut lock = self.inner.meta.lock().unwrap();
+        self.take_error(&mut lock)?;
+        let idx = lock.find(page);
+        if idx != NULL {
+            lock.queue.get_mut()[idx].1.get_mut()[..block.len()].copy_from_slice(block);
+            lock.rewritten |= idx == lock.front;
+        } else {
+            while lock.is_full() && lock.error.is_none() {
+                lock = self.inner.condvar.wait(lock).unwrap();
+            }
+            self.take_error(&mut lock)?;
+            lock.push(page, block);
+        }
+        let pos = page * Block::LEN as u64 + block.len() as u64;
+        if lock.len < pos {
+            lock.len = pos;
+        }
+        self.inner.condvar.notify_all();
         Ok(())
     }
 }
 
-impl<Source, Block, Queue, Table> Writer<Source> for AsyncIO<Source, Block, Queue, Table>
-    where Source: Read + Write + Seek + Send + Sync + 'static, Block: Array<u8> + Send + Sync + 'static, Queue: Array<(u64, Block)> + Send + Sync + 'static, Table: Array<(u64, usize)> + Send + Sync + 'static
+impl<Source, Block, Queue, Table, Timed> AsyncIO<Source, Block, Queue, Table, Timed>
+where
+    Source: Read + Write + Seek + Send + Sync,
+    Block: Array<u8> + Send + Sync,
+    Queue: Array<(u64, Block)> + Send + Sync,
+    Table: Array<(u64, usize)> + Send + Sync,
+    Timed: Bool,
 {
-    fn write(&mut self, _page: u64, _block: &[u8]) -> std::io::Result<()> {
-        self.check_error()?;
-        unimplemented!()
+    // Lets the worker drain the queue and waits for it to finish. Queued writes get one more try if
+    // the last one failed; they are lost if it fails again.
+    fn stop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            {
+                let mut lock = self.inner.meta.lock().unwrap();
+                lock.end = true;
+                lock.error = None;
+            }
+            self.inner.condvar.notify_all();
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<Source, Block, Queue, Table, Timed> Drop for AsyncIO<Source, Block, Queue, Table, Timed>
+where
+    Source: Read + Write + Seek + Send + Sync,
+    Block: Array<u8> + Send + Sync,
+    Queue: Array<(u64, Block)> + Send + Sync,
+    Table: Array<(u64, usize)> + Send + Sync,
+    Timed: Bool,
+{
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    type Block = Arr<u8, 16>;
+    type Async = AsyncIO<Cursor<Vec<u8>>, Block, Arr<(u64, Block), 4>, Arr<(u64, usize), 8>, True>;
+
+    // Fails writes while `fail` is set.
+    struct Flaky {
+        data: Cursor<Vec<u8>>,
+        fail: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.data.read(buf)
+        }
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    // Hands out at most 3 bytes per read and is interrupted every other call.
+    struct Trickle {
+        data: Cursor<Vec<u8>>,
+        interrupt: bool,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            let len = std::cmp::min(buf.len(), 3);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for Trickle {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    #[test]
+    fn short_reads() {
+        let data = (0..20).collect::<Vec<u8>>();
+        let io = ReadOnlyIO::<Trickle, Usize<16>, False>::new(Trickle {
+            data: Cursor::new(data.clone()),
+            interrupt: false,
+        })
+        .unwrap();
+        let mut block = [0xaa; 16];
+        io.read(0, &mut block).unwrap();
+        assert_eq!(&block[..], &data[..16]);
+
+        // The part of the last block past the end of the source reads as zeros.
+        let mut block = [0xaa; 16];
+        io.read(1, &mut block).unwrap();
+        assert_eq!(&block[..4], &data[16..]);
+        assert_eq!(&block[4..], &[0; 12]);
+
+        let mut block = [0xaa; 16];
+        io.read(5, &mut block).unwrap();
+        assert_eq!(block, [0; 16]);
+    }
+
+    #[test]
+    fn async_write() {
+        let io = Async::new(Cursor::new(vec![1; 40])).unwrap();
+        for page in 0..20 {
+            io.write(page, &[page as u8; 16]).unwrap();
+        }
+        io.write(20, &[0xff; 5]).unwrap();
+        assert_eq!(io.len(), 325);
+
+        let mut block = [0; 16];
+        io.read(7, &mut block).unwrap();
+        assert_eq!(block, [7; 16]);
+        io.read(20, &mut block[..5]).unwrap();
+        assert_eq!(&block[..5], &[0xff; 5]);
+
+        while io.latency().unwrap().write.count() < 21 {
+            std::thread::yield_now();
+        }
+        assert_eq!(io.queue_depth(), 0);
+        assert_eq!(io.latency().unwrap().queue_wait.count(), 21);
+
+        let data = io.into_inner().into_inner();
+        assert_eq!(data.len(), 325);
+        for page in 0..20 {
+            assert_eq!(&data[(page * 16)..(page * 16 + 16)], &[page as u8; 16]);
+        }
+        assert_eq!(&data[320..], &[0xff; 5]);
+    }
+
+    // Shares the data with the test, so it can be checked after the IO is gone.
+    struct Shared(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+    impl Read for Shared {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().read(buf)
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Shared {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.lock().unwrap().seek(pos)
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn async_drop() {
+        type SharedAsync =
+            AsyncIO<Shared, Block, Arr<(u64, Block), 4>, Arr<(u64, usize), 8>, False>;
+        let data = Arc::new(Mutex::new(Cursor::new(vec![0; 64])));
+        let io = SharedAsync::new(Shared(data.clone())).unwrap();
+        for page in 0..8 {
+            io.write(page, &[page as u8 + 1; 16]).unwrap();
+        }
+        drop(io);
+        // The worker has exited and dropped its handle on the source.
+        assert_eq!(Arc::strong_count(&data), 1);
+        let data = data.lock().unwrap().get_ref().clone();
+        for page in 0..8 {
+            assert_eq!(&data[(page * 16)..(page * 16 + 16)], &[page as u8 + 1; 16]);
+        }
+    }
+
+    #[test]
+    fn async_write_errors() {
+        type FlakyAsync = AsyncIO<Flaky, Block, Arr<(u64, Block), 4>, Arr<(u64, usize), 8>, False>;
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let data = Cursor::new(vec![0; 64]);
+        let io = FlakyAsync::new(Flaky {
+            data,
+            fail: fail.clone(),
+        })
+        .unwrap();
+        // Writers waiting on the full queue get the error instead of waiting forever.
+        let mut written = Vec::new();
+        let err = loop {
+            let page = written.len() as u64;
+            match io.write(page, &[page as u8 + 1; 16]) {
+                Ok(()) => written.push(page),
+                Err(e) => break e,
+            }
+        };
+        assert!(err.to_string().contains("page 0"));
+        assert!(!written.is_empty());
+
+        // Nothing that was accepted is dropped once the source recovers.
+        fail.store(false, std::sync::atomic::Ordering::SeqCst);
+        // A retry that failed in the meantime is reported once more.
+        if io.write(9, &[10; 16]).is_err() {
+            io.write(9, &[10; 16]).unwrap();
+        }
+        written.push(9);
+        let data = io.into_inner().data.into_inner();
+        for page in written {
+            let start = page as usize * 16;
+            assert_eq!(&data[start..(start + 16)], &[page as u8 + 1; 16]);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Log-linear buckets: values below `SUB` nanoseconds get a bucket each, and every power of two
+// above that is split into `SUB` linear sub-buckets, bounding the relative error to 1 / SUB.
+const SUB_BITS: u32 = 4;
+const SUB: u64 = 1 << SUB_BITS;
+const BUCKETS: usize = ((64 - SUB_BITS + 1) as usize) * SUB as usize;
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB {
+        return nanos as usize;
+    }
+    let shift = 63 - nanos.leading_zeros() - SUB_BITS;
+    let sub = nanos >> shift;
+    ((shift as u64 + 1) * SUB + (sub - SUB)) as usize
+}
+
+fn bucket_upper(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < SUB {
+        return idx;
+    }
+    let shift = idx / SUB - 1;
+    let low = (idx % SUB + SUB) << shift;
+    low + ((1 << shift) - 1)
+}
+
+pub struct LatencyHistogram {
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let nanos = std::cmp::min(latency.as_nanos(), u64::MAX as u128) as u64;
+        self.buckets[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> Duration {
+        Duration::from_nanos(self.sum.load(Ordering::Relaxed))
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max.load(Ordering::Relaxed))
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::from_nanos(0),
+            count => Duration::from_nanos(self.sum.load(Ordering::Relaxed) / count),
+        }
+    }
+
+    // Upper bound of the bucket holding the `p`th percentile, `p` in `0.0..=100.0`.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::from_nanos(0);
+        }
+        let rank = std::cmp::max(((p / 100.0) * count as f64).ceil() as u64, 1);
+        let mut seen = 0;
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= rank {
+                return std::cmp::min(Duration::from_nanos(bucket_upper(idx)), self.max());
+            }
+        }
+        self.max()
+    }
+
+    // `(upper bound, count)` of every non-empty bucket, in increasing order.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(idx, b)| {
+                (
+                    Duration::from_nanos(bucket_upper(idx)),
+                    b.load(Ordering::Relaxed),
+                )
+            })
+            .filter(|(_, count)| *count > 0)
+    }
+
+    pub fn clear(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+pub struct IOLatency {
+    pub read: LatencyHistogram,
+    pub write: LatencyHistogram,
+    pub queue_wait: LatencyHistogram,
+}
+
+impl IOLatency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        for nanos in (0..100_000)
+            .chain((0..64).map(|s| 1u64 << s))
+            .chain(Some(u64::MAX))
+        {
+            let idx = bucket_index(nanos);
+            assert!(idx < BUCKETS);
+            assert!(nanos <= bucket_upper(idx));
+            assert!(idx == 0 || nanos > bucket_upper(idx - 1));
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let hist = LatencyHistogram::new();
+        for us in 1..=1000 {
+            hist.record(Duration::from_micros(us));
+        }
+        assert_eq!(hist.count(), 1000);
+        assert_eq!(hist.max(), Duration::from_micros(1000));
+        for &(p, us) in &[(50.0, 500u64), (90.0, 900), (99.0, 990)] {
+            let v = hist.percentile(p).as_nanos() as f64;
+            let expected = (us * 1000) as f64;
+            assert!(v >= expected && v <= expected * (1.0 + 1.0 / SUB as f64));
+        }
+        assert_eq!(hist.percentile(100.0), hist.max());
+    }
+}
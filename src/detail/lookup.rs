@@ -1,8 +1,9 @@
 use super::*;
 
-const NULL: usize = std::usize::MAX;
-const NIL: u64 = std::u64::MAX;
-const DEL: u64 = NIL - 1;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const NULL: usize = usize::MAX;
+const NIL: u64 = u64::MAX;
 
 pub trait Lookup {
     const STATIC_META_MEM: usize;
@@ -12,6 +13,11 @@ pub trait Lookup {
     fn find(&self, page: u64) -> usize;
     fn insert(&mut self, page: u64, frame: usize);
     fn remove(&mut self, page: u64, frame_hint: usize);
+
+    // Way predictor hits and lookups so far, for lookups that predict.
+    fn way_predictions(&self) -> (u64, u64) {
+        (0, 0)
+    }
 }
 
 pub struct DMLookup {}
@@ -30,8 +36,197 @@ impl Lookup for DMLookup {
     fn remove(&mut self, _: u64, _: usize) {}
 }
 
+// Way prediction in front of any lookup: the pages last found or inserted are remembered in
+// `Entries` slots indexed by page hash and checked before `L::find`. With a single entry this is
+// an MRU way predictor. `find` only takes `&self` and may run concurrently, so every entry packs
+// page and frame into one atomic word, which can't tear. Pages from 2^48 and frames from
+// `PREDICT_FRAME_MASK` on aren't predicted.
+pub struct Predicted<L: Lookup, Entries: ConstUsize> {
+    inner: L,
+    entries: Vec<AtomicU64>,
+    hits: AtomicU64,
+    lookups: AtomicU64,
+    _phantom: std::marker::PhantomData<Entries>,
+}
+
+const PREDICT_FRAME_BITS: u32 = 16;
+const PREDICT_FRAME_MASK: u64 = (1 << PREDICT_FRAME_BITS) - 1;
+
+impl<L: Lookup, Entries: ConstUsize> Predicted<L, Entries> {
+    fn entry(page: u64) -> usize {
+        (hash64(page) % Entries::VALUE as u64) as usize
+    }
+
+    // The frame `word` predicts for `page`, if any. NIL never matches, its frame bits are the mask.
+    fn predicted(word: u64, page: u64) -> usize {
+        if word >> PREDICT_FRAME_BITS == page && word & PREDICT_FRAME_MASK != PREDICT_FRAME_MASK {
+            (word & PREDICT_FRAME_MASK) as usize
+        } else {
+            NULL
+        }
+    }
+
+    fn predict(&self, page: u64, frame: usize) {
+        if page >> (64 - PREDICT_FRAME_BITS) == 0 && (frame as u64) < PREDICT_FRAME_MASK {
+            let word = (page << PREDICT_FRAME_BITS) | frame as u64;
+            self.entries[Self::entry(page)].store(word, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<L: Lookup, Entries: ConstUsize> Lookup for Predicted<L, Entries> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>() - std::mem::size_of::<L>()
+        + L::STATIC_META_MEM
+        + std::mem::size_of::<u64>() * Entries::VALUE;
+    const META_MEM_PER_BLOCK: usize = L::META_MEM_PER_BLOCK;
+
+    fn new(count: usize) -> Self {
+        assert!(Entries::VALUE > 0, "io_cache Predicted needs an entry");
+        Self {
+            inner: L::new(count),
+            entries: (0..Entries::VALUE).map(|_| AtomicU64::new(NIL)).collect(),
+            hits: AtomicU64::new(0),
+            lookups: AtomicU64::new(0),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn find(&self, page: u64) -> usize {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let word = self.entries[Self::entry(page)].load(Ordering::Relaxed);
+        let frame = Self::predicted(word, page);
+        if frame != NULL {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return frame;
+        }
+        let frame = self.inner.find(page);
+        if frame != NULL {
+            self.predict(page, frame);
+        }
+        frame
+    }
+
+    fn insert(&mut self, page: u64, frame: usize) {
+        self.inner.insert(page, frame);
+        self.predict(page, frame);
+    }
+
+    fn remove(&mut self, page: u64, frame_hint: usize) {
+        let entry = &self.entries[Self::entry(page)];
+        if Self::predicted(entry.load(Ordering::Relaxed), page) != NULL {
+            entry.store(NIL, Ordering::Relaxed);
+        }
+        self.inner.remove(page, frame_hint);
+    }
+
+    fn way_predictions(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.lookups.load(Ordering::Relaxed),
+        )
+    }
+}
+
+// Robin Hood hashing with linear probing, shared by `Table` and `FATable`: entries are kept in
+// order of their distance from their home slot, so looking up a missing page stops as soon as it
+// passes the slot the page would have taken. Removals shift the following entries back instead of
+// leaving tombstones, so probe lengths only depend on the current load.
+fn rh_next(tbl: &[(u64, usize)], idx: usize) -> usize {
+    if idx + 1 == tbl.len() {
+        0
+    } else {
+        idx + 1
+    }
+}
+
+fn rh_dist<Slot: Fn(u64) -> usize>(tbl: &[(u64, usize)], idx: usize, slot: &Slot) -> usize {
+    (idx + tbl.len() - slot(tbl[idx].0)) % tbl.len()
+}
+
+fn rh_position<Slot: Fn(u64) -> usize>(tbl: &[(u64, usize)], page: u64, slot: Slot) -> usize {
+    let mut idx = slot(page);
+    for dist in 0..tbl.len() {
+        if tbl[idx].0 == NIL || rh_dist(tbl, idx, &slot) < dist {
+            return NULL;
+        }
+        if tbl[idx].0 == page {
+            return idx;
+        }
+        idx = rh_next(tbl, idx);
+    }
+    NULL
+}
+
+fn rh_find<Slot: Fn(u64) -> usize>(tbl: &[(u64, usize)], page: u64, slot: Slot) -> usize {
+    match rh_position(tbl, page, slot) {
+        NULL => NULL,
+        idx => tbl[idx].1,
+    }
+}
+
+fn rh_insert<Slot: Fn(u64) -> usize>(
+    tbl: &mut [(u64, usize)],
+    page: u64,
+    frame: usize,
+    slot: Slot,
+) {
+    let mut entry = (page, frame);
+    let mut idx = slot(page);
+    let mut dist = 0;
+    for _ in 0..tbl.len() {
+        if tbl[idx].0 == NIL {
+            tbl[idx] = entry;
+            return;
+        }
+        let other = rh_dist(tbl, idx, &slot);
+        if other < dist {
+            std::mem::swap(&mut entry, &mut tbl[idx]);
+            dist = other;
+        }
+        idx = rh_next(tbl, idx);
+        dist += 1;
+    }
+    panic!("io_cache lookup table is full");
+}
+
+// Returns whether `page` was found.
+fn rh_remove<Slot: Fn(u64) -> usize>(tbl: &mut [(u64, usize)], page: u64, slot: Slot) -> bool {
+    let mut idx = rh_position(tbl, page, &slot);
+    if idx == NULL {
+        return false;
+    }
+    loop {
+        let next = rh_next(tbl, idx);
+        if tbl[next].0 == NIL || rh_dist(tbl, next, &slot) == 0 {
+            tbl[idx] = (NIL, NULL);
+            return true;
+        }
+        tbl[idx] = tbl[next];
+        idx = next;
+    }
+}
+
 pub struct Table<Tbl: Array<(u64, usize)>> {
     table: Tbl,
+    len: usize,
+}
+
+impl<Tbl: Array<(u64, usize)>> Table<Tbl> {
+    fn slot(page: u64) -> usize {
+        (hash64(page) & (Tbl::LEN as u64 - 1)) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn load_factor(&self) -> f32 {
+        self.len as f32 / Tbl::LEN as f32
+    }
 }
 
 impl<Tbl: Array<(u64, usize)>> Lookup for Table<Tbl> {
@@ -40,70 +235,73 @@ impl<Tbl: Array<(u64, usize)>> Lookup for Table<Tbl> {
     const META_MEM_PER_BLOCK: usize = std::mem::size_of::<(u64, usize)>() * 2;
 
     fn new(_: usize) -> Self {
+        assert!(
+            Tbl::LEN.is_power_of_two(),
+            "io_cache Table size has to be a power of two"
+        );
         Self {
             table: Tbl::new_with((NIL, NULL)),
+            len: 0,
         }
     }
 
     fn find(&self, page: u64) -> usize {
-        let idx_init = (hash64(page) & (Tbl::LEN as u64 - 1)) as usize;
-        let mut idx = idx_init;
-        let tbl = self.table.get_ref();
-        {
-            let entry = &tbl[idx];
-            if entry.0 == NIL {
-                return NULL;
-            }
-            if entry.0 == page {
-                return entry.1;
-            }
-            idx = (idx + 1) & (Tbl::LEN - 1);
-        }
-        while idx != idx_init {
-            let entry = &tbl[idx];
-            if entry.0 == NIL {
-                return NULL;
-            }
-            if entry.0 == page {
-                return entry.1;
-            }
-            idx = (idx + 1) & (Tbl::LEN - 1);
-        }
-        return NULL;
+        rh_find(self.table.get_ref(), page, Self::slot)
     }
 
     fn insert(&mut self, page: u64, frame: usize) {
-        let mut idx = (hash64(page) & (Tbl::LEN as u64 - 1)) as usize;
-        let tbl = self.table.get_mut();
-        loop {
-            let entry = &mut tbl[idx];
-            if entry.0 == DEL || entry.0 == NIL {
-                entry.0 = page;
-                entry.1 = frame;
-                return;
-            }
-            idx = (idx + 1) & (Tbl::LEN - 1);
-        }
+        rh_insert(self.table.get_mut(), page, frame, Self::slot);
+        self.len += 1;
     }
 
     fn remove(&mut self, page: u64, _: usize) {
-        let mut idx = (hash64(page) & (Tbl::LEN as u64 - 1)) as usize;
-        let tbl = self.table.get_mut();
-        loop {
-            let entry = &mut tbl[idx];
-            if entry.0 == page {
-                entry.0 = DEL;
-                return;
-            }
-            idx = (idx + 1) & (Tbl::LEN - 1);
+        if rh_remove(self.table.get_mut(), page, Self::slot) {
+            self.len -= 1;
         }
     }
 }
 
+// Tags are compared a chunk of `TAG_CHUNK` at a time without branching, which the compiler turns
+// into vector compares, and only then checked for a match.
+const TAG_CHUNK: usize = 64;
+
+// Bit `i` is set if `tags[i] == page`, for up to `TAG_CHUNK` tags.
+fn tag_mask(tags: &[u64], page: u64) -> u64 {
+    let mut mask = 0;
+    for (i, tag) in tags.iter().enumerate() {
+        mask |= ((*tag == page) as u64) << i;
+    }
+    mask
+}
+
+fn tag_find(tags: &[u64], page: u64) -> usize {
+    for (idx, chunk) in tags.chunks(TAG_CHUNK).enumerate() {
+        let mask = tag_mask(chunk, page);
+        if mask != 0 {
+            return idx * TAG_CHUNK + mask.trailing_zeros() as usize;
+        }
+    }
+    NULL
+}
+
 pub struct Scan<Blocks: Array<u64>> {
     blocks: Blocks,
 }
 
+// Bitmasks over the first 64 frames.
+impl<Blocks: Array<u64>> Scan<Blocks> {
+    pub fn matches(&self, page: u64) -> u64 {
+        let tags = self.blocks.get_ref();
+        tag_mask(&tags[..std::cmp::min(tags.len(), TAG_CHUNK)], page)
+    }
+
+    pub fn occupied(&self) -> u64 {
+        let tags = self.blocks.get_ref();
+        let len = std::cmp::min(tags.len(), TAG_CHUNK);
+        !tag_mask(&tags[..len], NIL) & (u64::MAX >> (TAG_CHUNK - len))
+    }
+}
+
 impl<Blocks: Array<u64>> Lookup for Scan<Blocks> {
     const STATIC_META_MEM: usize =
         std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Blocks::LEN);
@@ -116,12 +314,7 @@ impl<Blocks: Array<u64>> Lookup for Scan<Blocks> {
     }
 
     fn find(&self, page: u64) -> usize {
-        for (f, p) in self.blocks.get_ref().iter().enumerate() {
-            if *p == page {
-                return f;
-            }
-        }
-        return NULL;
+        tag_find(self.blocks.get_ref(), page)
     }
 
     fn insert(&mut self, page: u64, frame: usize) {
@@ -140,6 +333,21 @@ impl<Blocks: Array<u64>> Lookup for Scan<Blocks> {
 
 pub struct FATable {
     table: Vec<(u64, usize)>,
+    len: usize,
+}
+
+impl FATable {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn load_factor(&self) -> f32 {
+        self.len as f32 / self.table.len() as f32
+    }
 }
 
 impl Lookup for FATable {
@@ -148,59 +356,196 @@ impl Lookup for FATable {
 
     fn new(count: usize) -> Self {
         Self {
-            table: vec![(NIL, NULL); (count * 3) / 2],
+            table: vec![(NIL, NULL); std::cmp::max((count * 3) / 2, 1)],
+            len: 0,
         }
     }
 
     fn find(&self, page: u64) -> usize {
-        let idx_init = (hash64(page) % self.table.len() as u64) as usize;
-        let mut idx = idx_init;
-        {
-            let entry = self.table[idx];
-            if entry.0 == NIL {
-                return NULL;
+        let size = self.table.len() as u64;
+        rh_find(&self.table, page, |page| (hash64(page) % size) as usize)
+    }
+
+    fn insert(&mut self, page: u64, frame: usize) {
+        let size = self.table.len() as u64;
+        rh_insert(&mut self.table, page, frame, |page| {
+            (hash64(page) % size) as usize
+        });
+        self.len += 1;
+    }
+
+    fn remove(&mut self, page: u64, _: usize) {
+        let size = self.table.len() as u64;
+        if rh_remove(&mut self.table, page, |page| (hash64(page) % size) as usize) {
+            self.len -= 1;
+        }
+    }
+}
+
+// Swiss table: a control byte per slot holds 7 bits of the page's hash, or marks the slot empty
+// or deleted, and slots hold frames while the pages live in a per-frame array. Slots are probed a
+// group of `GROUP` control bytes at a time, compared without branching so they vectorize, and
+// only slots whose control byte matches are checked against the page.
+const GROUP: usize = 16;
+const EMPTY: u8 = 0x80;
+const DELETED: u8 = 0xFE;
+
+// Bit `i` is set if `ctrl[i] == byte`.
+fn group_mask(ctrl: &[u8], byte: u8) -> u32 {
+    let mut mask = 0;
+    for (i, c) in ctrl.iter().enumerate() {
+        mask |= ((*c == byte) as u32) << i;
+    }
+    mask
+}
+
+pub struct FASwiss {
+    ctrl: Vec<u8>,
+    slots: Vec<u32>,
+    pages: Vec<u64>,
+    len: usize,
+    // Empty slots that may still be filled before the table has to be rebuilt, keeping the load
+    // including deleted slots at most 7/8.
+    growth_left: usize,
+}
+
+impl FASwiss {
+    fn groups(&self) -> usize {
+        self.ctrl.len() / GROUP
+    }
+
+    fn max_load(&self) -> usize {
+        self.ctrl.len() - self.ctrl.len() / 8
+    }
+
+    fn hash(page: u64) -> (usize, u8) {
+        let hash = hash64(page);
+        ((hash >> 7) as usize, (hash & 0x7F) as u8)
+    }
+
+    // Slot holding `page`, or NULL.
+    fn position(&self, page: u64) -> usize {
+        let (h1, h2) = Self::hash(page);
+        let groups = self.groups();
+        for probe in 0..groups {
+            let start = ((h1 + probe) % groups) * GROUP;
+            let ctrl = &self.ctrl[start..(start + GROUP)];
+            let mut mask = group_mask(ctrl, h2);
+            while mask != 0 {
+                let slot = start + mask.trailing_zeros() as usize;
+                if self.pages[self.slots[slot] as usize] == page {
+                    return slot;
+                }
+                mask &= mask - 1;
             }
-            if entry.0 == page {
-                return entry.1;
+            if group_mask(ctrl, EMPTY) != 0 {
+                return NULL;
             }
-            idx = (idx + 1) % self.table.len();
         }
-        while idx != idx_init {
-            let entry = self.table[idx];
-            if entry.0 == NIL {
-                return NULL;
+        NULL
+    }
+
+    fn place(&mut self, page: u64, frame: usize) {
+        let (h1, h2) = Self::hash(page);
+        let groups = self.groups();
+        for probe in 0..groups {
+            let start = ((h1 + probe) % groups) * GROUP;
+            let ctrl = &self.ctrl[start..(start + GROUP)];
+            let mask = group_mask(ctrl, EMPTY) | group_mask(ctrl, DELETED);
+            if mask != 0 {
+                let slot = start + mask.trailing_zeros() as usize;
+                if self.ctrl[slot] == EMPTY {
+                    self.growth_left -= 1;
+                }
+                self.ctrl[slot] = h2;
+                self.slots[slot] = frame as u32;
+                return;
             }
-            if entry.0 == page {
-                return entry.1;
+        }
+        panic!("io_cache lookup table is full");
+    }
+
+    // Clears the deleted slots by inserting every cached page again.
+    fn rebuild(&mut self) {
+        for c in self.ctrl.iter_mut() {
+            *c = EMPTY;
+        }
+        self.growth_left = self.max_load();
+        for frame in 0..self.pages.len() {
+            if self.pages[frame] != NIL {
+                self.place(self.pages[frame], frame);
             }
-            idx = (idx + 1) % self.table.len();
         }
-        return NULL;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn load_factor(&self) -> f32 {
+        self.len as f32 / self.ctrl.len() as f32
+    }
+}
+
+impl Lookup for FASwiss {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>()
+        + (std::mem::size_of::<u8>() + std::mem::size_of::<u32>()) * GROUP;
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<u64>()
+        + ((std::mem::size_of::<u8>() + std::mem::size_of::<u32>()) * 8).div_ceil(7);
+
+    fn new(count: usize) -> Self {
+        assert!(
+            count <= u32::MAX as usize,
+            "io_cache FASwiss holds at most u32::MAX frames"
+        );
+        let slots = std::cmp::max((count * 8).div_ceil(7).div_ceil(GROUP) * GROUP, GROUP);
+        let mut ret = Self {
+            ctrl: vec![EMPTY; slots],
+            slots: vec![0; slots],
+            pages: vec![NIL; count],
+            len: 0,
+            growth_left: 0,
+        };
+        ret.growth_left = ret.max_load();
+        ret
+    }
+
+    fn find(&self, page: u64) -> usize {
+        match self.position(page) {
+            NULL => NULL,
+            slot => self.slots[slot] as usize,
+        }
     }
 
     fn insert(&mut self, page: u64, frame: usize) {
-        let mut idx = (hash64(page) % self.table.len() as u64) as usize;
-        loop {
-            let entry = &mut self.table[idx];
-            if entry.0 == DEL || entry.0 == NIL {
-                entry.0 = page;
-                entry.1 = frame;
-                return;
-            }
-            idx = (idx + 1) % self.table.len();
+        if self.growth_left == 0 {
+            self.rebuild();
         }
+        self.pages[frame] = page;
+        self.place(page, frame);
+        self.len += 1;
     }
 
+    // A slot can go back to empty if its group has an empty slot already, as no probe went past
+    // the group then. Otherwise it's marked deleted so probes carry on.
     fn remove(&mut self, page: u64, _: usize) {
-        let mut idx = (hash64(page) % self.table.len() as u64) as usize;
-        loop {
-            let entry = &mut self.table[idx];
-            if entry.0 == page {
-                entry.0 = DEL;
-                return;
-            }
-            idx = (idx + 1) % self.table.len();
+        let slot = self.position(page);
+        if slot == NULL {
+            return;
         }
+        let start = slot / GROUP * GROUP;
+        if group_mask(&self.ctrl[start..(start + GROUP)], EMPTY) != 0 {
+            self.ctrl[slot] = EMPTY;
+            self.growth_left += 1;
+        } else {
+            self.ctrl[slot] = DELETED;
+        }
+        self.pages[self.slots[slot] as usize] = NIL;
+        self.len -= 1;
     }
 }
 
@@ -208,6 +553,18 @@ pub struct FAScan {
     blocks: Vec<u64>,
 }
 
+impl FAScan {
+    pub fn matches(&self, page: u64) -> u64 {
+        let len = std::cmp::min(self.blocks.len(), TAG_CHUNK);
+        tag_mask(&self.blocks[..len], page)
+    }
+
+    pub fn occupied(&self) -> u64 {
+        let len = std::cmp::min(self.blocks.len(), TAG_CHUNK);
+        !tag_mask(&self.blocks[..len], NIL) & (u64::MAX >> (TAG_CHUNK - len))
+    }
+}
+
 impl Lookup for FAScan {
     const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
     const META_MEM_PER_BLOCK: usize = std::mem::size_of::<u64>();
@@ -219,12 +576,7 @@ impl Lookup for FAScan {
     }
 
     fn find(&self, page: u64) -> usize {
-        for (f, p) in self.blocks.iter().enumerate() {
-            if *p == page {
-                return f;
-            }
-        }
-        return NULL;
+        tag_find(&self.blocks, page)
     }
 
     fn insert(&mut self, page: u64, frame: usize) {
@@ -240,3 +592,122 @@ impl Lookup for FAScan {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Churns through random inserts and removals, checking against a `HashMap`.
+    fn churn<L: Lookup>(mut l: L, count: usize) -> L {
+        let mut model = std::collections::HashMap::new();
+        let mut x = 1u64;
+        for frame in (0..count).cycle().take(20000) {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let page = (x >> 33) % 1000;
+            let old = model.iter().find(|(_, f)| **f == frame).map(|(p, _)| *p);
+            if let Some(old) = old {
+                l.remove(old, frame);
+                model.remove(&old);
+            }
+            if let std::collections::hash_map::Entry::Vacant(e) = model.entry(page) {
+                l.insert(page, frame);
+                e.insert(frame);
+            }
+            assert_eq!(l.find(page), model[&page]);
+            assert_eq!(l.find(page + 1000), NULL);
+        }
+        for page in 0..1000 {
+            assert_eq!(l.find(page), model.get(&page).copied().unwrap_or(NULL));
+        }
+        l
+    }
+
+    #[test]
+    fn predicted() {
+        let mut p = Predicted::<Scan<Arr<u64, 16>>, Usize<1>>::new(16);
+        p.insert(3, 5);
+        p.insert(4, 6);
+        assert_eq!(p.find(4), 6);
+        assert_eq!(p.find(3), 5);
+        assert_eq!(p.find(3), 5);
+        assert_eq!(p.find(9), NULL);
+        assert_eq!(p.way_predictions(), (2, 4));
+        // Removed pages aren't predicted into a frame that was reused.
+        p.remove(3, 5);
+        p.insert(7, 5);
+        assert_eq!(p.find(3), NULL);
+        // Pages too large to pack are looked up without a prediction.
+        let big = 1 << 50;
+        p.insert(big, 2);
+        assert_eq!(p.find(big), 2);
+        assert_eq!(p.way_predictions(), (2, 6));
+        churn(Predicted::<FATable, Usize<4>>::new(12), 12);
+    }
+
+    #[test]
+    fn swiss() {
+        let s = churn(FASwiss::new(10), 10);
+        assert!(s.len() <= 10);
+        let s = churn(FASwiss::new(100), 100);
+        assert!(s.load_factor() <= 7.0 / 8.0);
+        // Deleted slots are reclaimed, full groups or not.
+        let mut s = FASwiss::new(14);
+        for round in 0..100u64 {
+            for frame in 0..14 {
+                s.insert(round * 14 + frame as u64, frame);
+            }
+            for frame in 0..14 {
+                assert_eq!(s.find(round * 14 + frame as u64), frame);
+                s.remove(round * 14 + frame as u64, frame);
+            }
+        }
+        assert!(s.is_empty());
+        assert_eq!(s.find(0), NULL);
+    }
+
+    #[test]
+    fn scan() {
+        let mut s = Scan::<Arr<u64, 40>>::new(40);
+        assert_eq!(s.occupied(), 0);
+        s.insert(7, 3);
+        s.insert(9, 39);
+        assert_eq!(s.find(9), 39);
+        assert_eq!(s.find(8), NULL);
+        assert_eq!(s.matches(7), 1 << 3);
+        assert_eq!(s.occupied(), (1 << 3) | (1 << 39));
+        s.remove(7, NULL);
+        assert_eq!(s.find(7), NULL);
+
+        let mut s = FAScan::new(100);
+        s.insert(5, 70);
+        s.insert(6, 63);
+        assert_eq!(s.find(5), 70);
+        assert_eq!(s.find(6), 63);
+        assert_eq!(s.matches(5), 0);
+        assert_eq!(s.occupied(), 1 << 63);
+        let s = churn(FAScan::new(70), 70);
+        let occupied = s.blocks[..64].iter().filter(|tag| **tag != NIL).count();
+        assert_eq!(s.occupied().count_ones() as usize, occupied);
+    }
+
+    #[test]
+    fn table() {
+        let t = churn(Table::<Arr<(u64, usize), 16>>::new(8), 8);
+        assert!(t.len() <= 8);
+        assert!(t.load_factor() <= 0.5);
+        let t = churn(FATable::new(10), 10);
+        assert!(t.len() <= 10);
+        // Without tombstones removing everything leaves an empty table.
+        let mut t = FATable::new(4);
+        for page in 0..4 {
+            t.insert(page, page as usize);
+        }
+        for page in 0..4 {
+            t.remove(page, page as usize);
+        }
+        assert!(t.is_empty());
+        assert!(t.table.iter().all(|entry| entry.0 == NIL));
+    }
+}
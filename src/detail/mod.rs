@@ -1,16 +1,22 @@
+mod admission;
 mod consts;
 mod inner_mut;
+mod io;
+mod latency;
 mod lookup;
+mod policy;
 mod replace;
 mod set;
-mod io;
 
+pub use admission::*;
 pub use consts::*;
 pub use inner_mut::*;
+pub use io::*;
+pub use latency::*;
 pub use lookup::*;
+pub use policy::*;
 pub use replace::*;
 pub use set::*;
-pub use io::*;
 
 /*
 const fn hash32(x: u32) -> u32 {
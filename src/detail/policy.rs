@@ -0,0 +1,221 @@
+use super::*;
+
+const NIL: u64 = u64::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candidate {
+    pub frame: usize,
+    pub page: u64,
+}
+
+// Replacement policies written outside of this crate, told which page every frame holds. Plug one
+// into a set through `Custom<P>`, or `Custom<Boxed<F>>` to pick the policy at run time.
+pub trait ReplacePolicy {
+    fn new(count: usize) -> Self
+    where
+        Self: Sized;
+
+    fn insert(&mut self, _frame: usize, _page: u64) {}
+    fn access(&mut self, _frame: usize, _page: u64) {}
+    fn remove(&mut self, _frame: usize, _page: u64) {}
+
+    // Whether `page`, held by `frame`, may be evicted to make room for `for_page`, e.g. to keep
+    // index pages over data pages.
+    fn evictable(&self, _frame: usize, _page: u64, _for_page: u64) -> bool {
+        true
+    }
+
+    // Returns the frame to evict for `page`, one of `candidates`. Only called once every frame is
+    // used, with the evictable frames or all of them if none is.
+    fn choose_victim(&mut self, page: u64, candidates: &[Candidate]) -> usize;
+}
+
+pub trait PolicyFactory {
+    fn build(count: usize) -> Box<dyn ReplacePolicy + Send>;
+}
+
+pub struct Boxed<F: PolicyFactory> {
+    policy: Box<dyn ReplacePolicy + Send>,
+    _phantom: std::marker::PhantomData<F>,
+}
+
+impl<F: PolicyFactory> ReplacePolicy for Boxed<F> {
+    fn new(count: usize) -> Self {
+        Self {
+            policy: F::build(count),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn insert(&mut self, frame: usize, page: u64) {
+        self.policy.insert(frame, page)
+    }
+
+    fn access(&mut self, frame: usize, page: u64) {
+        self.policy.access(frame, page)
+    }
+
+    fn remove(&mut self, frame: usize, page: u64) {
+        self.policy.remove(frame, page)
+    }
+
+    fn evictable(&self, frame: usize, page: u64, for_page: u64) -> bool {
+        self.policy.evictable(frame, page, for_page)
+    }
+
+    fn choose_victim(&mut self, page: u64, candidates: &[Candidate]) -> usize {
+        self.policy.choose_victim(page, candidates)
+    }
+}
+
+pub struct Custom<P: ReplacePolicy> {
+    policy: P,
+    pages: Vec<u64>,
+    free: Vec<usize>,
+    candidates: Vec<Candidate>,
+}
+
+impl<P: ReplacePolicy> Custom<P> {
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+}
+
+impl<P: ReplacePolicy> Replace for Custom<P> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<u64>()
+        + std::mem::size_of::<usize>()
+        + std::mem::size_of::<Candidate>();
+
+    fn new(count: usize) -> Self {
+        Self {
+            policy: P::new(count),
+            pages: vec![NIL; count],
+            free: (0..count).rev().collect(),
+            candidates: Vec::with_capacity(count),
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        match self.free.last() {
+            Some(frame) => *frame,
+            None => {
+                self.candidates.clear();
+                for (frame, p) in self.pages.iter().enumerate() {
+                    if self.policy.evictable(frame, *p, page) {
+                        self.candidates.push(Candidate { frame, page: *p });
+                    }
+                }
+                if self.candidates.is_empty() {
+                    let all = self.pages.iter().enumerate();
+                    self.candidates
+                        .extend(all.map(|(frame, p)| Candidate { frame, page: *p }));
+                }
+                let frame = self.policy.choose_victim(page, &self.candidates);
+                assert!(
+                    self.candidates.iter().any(|c| c.frame == frame),
+                    "io_cache custom policy chose a frame that is not a candidate"
+                );
+                frame
+            }
+        }
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.policy.access(idx, self.pages[idx]);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        if let Some(pos) = self.free.iter().rposition(|f| *f == idx) {
+            self.free.remove(pos);
+        } else if self.pages[idx] != NIL {
+            self.policy.remove(idx, self.pages[idx]);
+        }
+        self.pages[idx] = page;
+        self.policy.insert(idx, page);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        if self.pages[idx] != NIL {
+            self.policy.remove(idx, self.pages[idx]);
+            self.pages[idx] = NIL;
+            self.free.push(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Evicts in insertion order but never evicts even (index) pages for odd (data) ones.
+    struct KeepIndex {
+        inserted: Vec<u64>,
+        now: u64,
+    }
+
+    impl ReplacePolicy for KeepIndex {
+        fn new(count: usize) -> Self {
+            Self {
+                inserted: vec![0; count],
+                now: 0,
+            }
+        }
+
+        fn insert(&mut self, frame: usize, _: u64) {
+            self.now += 1;
+            self.inserted[frame] = self.now;
+        }
+
+        fn evictable(&self, _: usize, page: u64, for_page: u64) -> bool {
+            !page.is_multiple_of(2) || for_page.is_multiple_of(2)
+        }
+
+        fn choose_victim(&mut self, _: u64, candidates: &[Candidate]) -> usize {
+            let oldest = candidates.iter().min_by_key(|c| self.inserted[c.frame]);
+            oldest.unwrap().frame
+        }
+    }
+
+    struct KeepIndexFactory;
+
+    impl PolicyFactory for KeepIndexFactory {
+        fn build(count: usize) -> Box<dyn ReplacePolicy + Send> {
+            Box::new(KeepIndex::new(count))
+        }
+    }
+
+    fn insert<R: Replace>(r: &mut R, page: u64) -> usize {
+        let frame = r.replace(page);
+        r.record_insert(frame, page);
+        frame
+    }
+
+    fn keeps_index<R: Replace>(mut r: R) {
+        for page in 0..4 {
+            assert_eq!(insert(&mut r, page), page as usize);
+        }
+        // Data pages only displace data pages, index pages go by insertion order.
+        assert_eq!(insert(&mut r, 5), 1);
+        assert_eq!(insert(&mut r, 7), 3);
+        assert_eq!(insert(&mut r, 9), 1);
+        assert_eq!(insert(&mut r, 10), 0);
+        // Emptied frames are used before evicting anything.
+        r.record_remove(2);
+        r.record_remove(0);
+        assert_eq!(insert(&mut r, 11), 0);
+        assert_eq!(insert(&mut r, 12), 2);
+        // Refilling an emptied frame directly takes it off the free list.
+        r.record_remove(3);
+        r.record_remove(1);
+        r.record_insert(3, 14);
+        assert_eq!(insert(&mut r, 15), 1);
+        assert_eq!(insert(&mut r, 17), 0);
+    }
+
+    #[test]
+    fn custom() {
+        keeps_index(Custom::<KeepIndex>::new(4));
+        keeps_index(Custom::<Boxed<KeepIndexFactory>>::new(4));
+    }
+}
@@ -1,14 +1,44 @@
 use super::*;
 
-const NULL: usize = std::usize::MAX;
+const NULL: usize = usize::MAX;
+const NIL: u64 = u64::MAX;
 
 pub trait Replace {
     const STATIC_META_MEM: usize;
     const META_MEM_PER_BLOCK: usize;
+    // Whether misses should be timed and passed to `record_cost`.
+    const COST_AWARE: bool = false;
 
     fn new(count: usize) -> Self;
-    fn replace(&mut self) -> usize;
+    fn replace(&mut self, page: u64) -> usize;
     fn record_access(&mut self, block_idx: usize);
+
+    // `page` was just cached in `block_idx`, as returned by `replace` or emptied by
+    // `record_remove`, in which case the frame is set up as if `replace` had returned it.
+    fn record_insert(&mut self, _block_idx: usize, _page: u64) {}
+
+    // `block_idx` was emptied without being reused, e.g. by `IOCache::invalidate`. Policies hand
+    // empty frames out before evicting any cached page.
+    fn record_remove(&mut self, _block_idx: usize) {}
+
+    // `block_idx` was written to or, with `dirty` false, written back. Fresh frames are clean.
+    fn record_dirty(&mut self, _block_idx: usize, _dirty: bool) {}
+
+    // Right after `record_insert`, what it took to fetch the page, from `Reader::miss_cost`.
+    fn record_cost(&mut self, _block_idx: usize, _cost: u64) {}
+
+    // Whether `page`, which just missed and was given `victim` by `replace`, should be cached
+    // there. Only asked on the read path; declined pages are served without being cached.
+    fn admit(&mut self, _page: u64, _victim: usize) -> bool {
+        true
+    }
+
+    // Called by `NWaySets` on every set's policy with the set's index and the number of sets.
+    fn join_set(&mut self, _set: usize, _sets: usize) {}
+
+    // Called by `NWaySets` on every set's policy with the set's index, the number of sets and a
+    // selector shared by all of them, for policies dueling sets against each other.
+    fn join_duel(&mut self, _set: usize, _sets: usize, _duel: &std::sync::Arc<SetDuel>) {}
 }
 
 pub struct DMReplace {}
@@ -20,48 +50,175 @@ impl Replace for DMReplace {
     fn new(_: usize) -> Self {
         Self {}
     }
-    fn replace(&mut self) -> usize {
+    fn replace(&mut self, _: u64) -> usize {
         0
     }
     fn record_access(&mut self, _: usize) {}
 }
 
-pub struct Random<Size: ConstUsize> {
-    seed: usize,
+// Random number generators for `Random` and `FARandom`. Every set gets its own stream, numbered
+// by the set's index.
+pub trait RandomSource {
+    fn new(stream: u64) -> Self;
+    fn next_u64(&mut self) -> u64;
+}
+
+// xorshift64* seeded from `Seed` and the stream number.
+pub struct XorShift<Seed: ConstUsize> {
+    state: u64,
+    _phantom: std::marker::PhantomData<Seed>,
+}
+
+impl<Seed: ConstUsize> RandomSource for XorShift<Seed> {
+    fn new(stream: u64) -> Self {
+        let state = hash64(Seed::VALUE as u64 ^ hash64(stream));
+        Self {
+            state: std::cmp::max(state, 1),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = self.state;
+        let s = s ^ (s >> 12);
+        let s = s ^ (s << 25);
+        let s = s ^ (s >> 27);
+        self.state = s;
+        s.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+}
+
+// Uniform in `0..n`, by multiplying into the high word and rejecting the few low words that would
+// favour some results.
+fn random_below<Rng: RandomSource>(rng: &mut Rng, n: usize) -> usize {
+    let n = n as u64;
+    let threshold = n.wrapping_neg() % n;
+    loop {
+        let m = rng.next_u64() as u128 * n as u128;
+        if m as u64 >= threshold {
+            return (m >> 64) as usize;
+        }
+    }
+}
+
+// Frames without a page, for policies whose own state can't tell them apart from cached ones.
+// `replace` hands them out lowest first, before evicting anything.
+struct FreeFrames {
+    bits: Vec<u64>,
+    len: usize,
+}
+
+impl FreeFrames {
+    // The bitmap lives on the heap, so `size_of` of the owner misses it. `FA*` policies only
+    // know their frame count at runtime and charge its bit per block, rounded up to a byte.
+    const MEM_PER_BLOCK: usize = 1;
+
+    const fn mem(count: usize) -> usize {
+        count.div_ceil(64) * std::mem::size_of::<u64>()
+    }
+
+    // For policies that fill unused frames in order anyway and only need to track the frames
+    // emptied by `record_remove`.
+    fn empty(count: usize) -> Self {
+        Self {
+            bits: vec![0; count.div_ceil(64)],
+            len: 0,
+        }
+    }
+
+    fn full(count: usize) -> Self {
+        let mut ret = Self::empty(count);
+        for idx in 0..count {
+            ret.put(idx);
+        }
+        ret
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let word = self.bits.iter().position(|bits| *bits != 0)?;
+        let idx = word * 64 + self.bits[word].trailing_zeros() as usize;
+        self.take(idx);
+        Some(idx)
+    }
+
+    // Returns whether `idx` was free.
+    fn take(&mut self, idx: usize) -> bool {
+        let mask = 1 << (idx % 64);
+        let free = self.bits[idx / 64] & mask != 0;
+        if free {
+            self.bits[idx / 64] &= !mask;
+            self.len -= 1;
+        }
+        free
+    }
+
+    fn put(&mut self, idx: usize) {
+        let mask = 1 << (idx % 64);
+        if self.bits[idx / 64] & mask == 0 {
+            self.bits[idx / 64] |= mask;
+            self.len += 1;
+        }
+    }
+}
+
+// `FreeFrames` for policies of at most 64 ways, kept inline in a mask.
+fn pop_free(free: &mut u64) -> Option<usize> {
+    if *free == 0 {
+        return None;
+    }
+    let idx = free.trailing_zeros() as usize;
+    *free &= !(1 << idx);
+    Some(idx)
+}
+
+fn take_free(free: &mut u64, idx: usize) -> bool {
+    let was_free = *free & (1 << idx) != 0;
+    *free &= !(1 << idx);
+    was_free
+}
+
+pub struct Random<Size: ConstUsize, Rng: RandomSource> {
+    rng: Rng,
+    free: FreeFrames,
     _phantom: std::marker::PhantomData<Size>,
 }
 
-impl<Size: ConstUsize> Replace for Random<Size> {
-    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+impl<Size: ConstUsize, Rng: RandomSource> Replace for Random<Size, Rng> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>() + FreeFrames::mem(Size::VALUE);
     const META_MEM_PER_BLOCK: usize = 0;
 
     fn new(_: usize) -> Self {
+        assert!(Size::VALUE > 0, "io_cache Random needs at least one way");
         Self {
-            seed: 0x981234,
+            rng: Rng::new(0),
+            free: FreeFrames::full(Size::VALUE),
             _phantom: std::marker::PhantomData,
         }
     }
 
-    fn replace(&mut self) -> usize {
-        let s = self.seed;
-        if std::mem::size_of::<usize>() == std::mem::size_of::<u32>() {
-            let s = s ^ (s << 13);
-            let s = s ^ (s >> 17);
-            let s = s ^ (s << 5);
-            self.seed = s;
-            s & (Size::VALUE - 1)
-        } else if std::mem::size_of::<usize>() == std::mem::size_of::<u64>() {
-            let s = s ^ (s << 13);
-            let s = s ^ (s >> 7);
-            let s = s ^ (s << 17);
-            self.seed = s;
-            s & (Size::VALUE - 1)
-        } else {
-            panic!("io_cache random replacement not supported on this architecture");
+    fn replace(&mut self, _: u64) -> usize {
+        match self.free.pop() {
+            Some(frame) => frame,
+            None => random_below(&mut self.rng, Size::VALUE),
         }
     }
 
     fn record_access(&mut self, _: usize) {}
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.free.take(idx);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.free.put(idx);
+    }
+
+    fn join_set(&mut self, set: usize, _: usize) {
+        self.rng = Rng::new(set as u64);
+    }
 }
 
 pub struct LRU<Data: Array<LRUMeta>> {
@@ -102,7 +259,7 @@ impl<Data: Array<LRUMeta>> Replace for LRU<Data> {
         }
     }
 
-    fn replace(&mut self) -> usize {
+    fn replace(&mut self, _: u64) -> usize {
         let ret = self.front;
         let next = self.data.get_ref()[ret].next;
         if next != NULL {
@@ -153,6 +310,107 @@ impl<Data: Array<LRUMeta>> Replace for LRU<Data> {
             }
         }
     }
+
+    fn record_insert(&mut self, block_idx: usize, _: u64) {
+        self.record_access(block_idx);
+    }
+
+    fn record_remove(&mut self, block_idx: usize) {
+        let front = self.front;
+        if front != block_idx {
+            let data = self.data.get_mut();
+            let (next, prev) = (data[block_idx].next, data[block_idx].prev);
+            data[prev].next = next;
+            if next == NULL {
+                self.back = prev;
+            } else {
+                data[next].prev = prev;
+            }
+            data[block_idx].prev = NULL;
+            data[block_idx].next = front;
+            data[front].prev = block_idx;
+            self.front = block_idx;
+        }
+    }
+}
+
+// Clean-first LRU: looks for a clean victim among the `Window` fraction of least recently used
+// frames, so reads don't wait on write-backs, and only takes the LRU frame if they're all dirty.
+pub struct CFLRU<Data: Array<LRUMeta>, Dirty: Array<bool>, Window: ConstF32> {
+    lru: LRU<Data>,
+    dirty: Dirty,
+    window: usize,
+    _phantom: std::marker::PhantomData<Window>,
+}
+
+fn cflru_window<Window: ConstF32>(count: usize) -> usize {
+    let window = (count as f32 * Window::VALUE).ceil() as usize;
+    std::cmp::min(std::cmp::max(window, 1), count)
+}
+
+// The first clean frame among the `window` ones from `front`, or `front` if there is none.
+fn cflru_victim(list: &[LRUMeta], dirty: &[bool], front: usize, window: usize) -> usize {
+    let mut idx = front;
+    for _ in 0..window {
+        if idx == NULL {
+            break;
+        }
+        if !dirty[idx] {
+            return idx;
+        }
+        idx = list[idx].next;
+    }
+    front
+}
+
+impl<Data: Array<LRUMeta>, Dirty: Array<bool>, Window: ConstF32> Replace
+    for CFLRU<Data, Dirty, Window>
+{
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Data::LEN);
+    const META_MEM_PER_BLOCK: usize = LRU::<Data>::META_MEM_PER_BLOCK + std::mem::size_of::<bool>();
+
+    fn new(count: usize) -> Self {
+        assert!(
+            Data::LEN == Dirty::LEN,
+            "io_cache CFLRU arrays differ in size"
+        );
+        Self {
+            lru: LRU::new(count),
+            dirty: Dirty::new(),
+            window: cflru_window::<Window>(Data::LEN),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        let dirty = self.dirty.get_ref();
+        let ret = cflru_victim(self.lru.data.get_ref(), dirty, self.lru.front, self.window);
+        if ret == self.lru.front {
+            self.lru.replace(page)
+        } else {
+            self.lru.record_access(ret);
+            ret
+        }
+    }
+
+    fn record_access(&mut self, block_idx: usize) {
+        self.lru.record_access(block_idx);
+    }
+
+    fn record_insert(&mut self, block_idx: usize, page: u64) {
+        self.dirty.get_mut()[block_idx] = false;
+        self.lru.record_insert(block_idx, page);
+    }
+
+    fn record_remove(&mut self, block_idx: usize) {
+        self.dirty.get_mut()[block_idx] = false;
+        self.lru.record_remove(block_idx);
+    }
+
+    fn record_dirty(&mut self, block_idx: usize, dirty: bool) {
+        self.dirty.get_mut()[block_idx] = dirty;
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -161,6 +419,81 @@ pub struct HeapElem<T> {
     pos: usize,
 }
 
+// Binary min-heap over the frames: `queue[pos]` is the frame at heap position `pos` and
+// `elems[frame].pos` its position, kept in sync on every swap.
+fn heap_init<T>(elems: &mut [HeapElem<T>], queue: &mut [usize]) {
+    for (idx, elem) in elems.iter_mut().enumerate() {
+        elem.pos = idx;
+        queue[idx] = idx;
+    }
+}
+
+fn heap_swap<T>(elems: &mut [HeapElem<T>], queue: &mut [usize], a: usize, b: usize) {
+    queue.swap(a, b);
+    elems[queue[a]].pos = a;
+    elems[queue[b]].pos = b;
+}
+
+// Restores the heap order after the key of `idx` changed in either direction.
+fn heap_fix<T, Cmp: Fn(&T, &T) -> i8>(
+    elems: &mut [HeapElem<T>],
+    queue: &mut [usize],
+    idx: usize,
+    cmp: Cmp,
+) {
+    let mut pos = elems[idx].pos;
+    while pos > 0 {
+        let parent = (pos - 1) / 2;
+        if cmp(&elems[queue[pos]].data, &elems[queue[parent]].data) >= 0 {
+            break;
+        }
+        heap_swap(elems, queue, pos, parent);
+        pos = parent;
+    }
+    heap_sift_down(elems, queue, pos, &cmp);
+}
+
+fn heap_sift_down<T, Cmp: Fn(&T, &T) -> i8>(
+    elems: &mut [HeapElem<T>],
+    queue: &mut [usize],
+    mut pos: usize,
+    cmp: &Cmp,
+) {
+    let less = |elems: &[HeapElem<T>], a: usize, b: usize| cmp(&elems[a].data, &elems[b].data) < 0;
+    loop {
+        let left = pos * 2 + 1;
+        if left >= queue.len() {
+            return;
+        }
+        let right = left + 1;
+        let child = if right < queue.len() && less(elems, queue[right], queue[left]) {
+            right
+        } else {
+            left
+        };
+        if !less(elems, queue[child], queue[pos]) {
+            return;
+        }
+        heap_swap(elems, queue, pos, child);
+        pos = child;
+    }
+}
+
+// Applies `f` to every key and restores the heap order from scratch.
+fn heap_update_all<T, F: FnMut(&mut T), Cmp: Fn(&T, &T) -> i8>(
+    elems: &mut [HeapElem<T>],
+    queue: &mut [usize],
+    mut f: F,
+    cmp: Cmp,
+) {
+    for elem in elems.iter_mut() {
+        f(&mut elem.data);
+    }
+    for pos in (0..queue.len() / 2).rev() {
+        heap_sift_down(elems, queue, pos, &cmp);
+    }
+}
+
 struct Heap<T: Clone + Default, Data: Array<HeapElem<T>>, Queue: Array<usize>> {
     data: Data,
     queue: Queue,
@@ -168,22 +501,21 @@ struct Heap<T: Clone + Default, Data: Array<HeapElem<T>>, Queue: Array<usize>> {
 }
 
 impl<T: Clone + Default, Data: Array<HeapElem<T>>, Queue: Array<usize>> Heap<T, Data, Queue> {
-    /*
-    const STATIC_META_MEM: usize =
-        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Data::LEN);
-    */
     const META_MEM_PER_BLOCK: usize =
         std::mem::size_of::<HeapElem<T>>() + std::mem::size_of::<usize>();
 
+    // The keys set by `f` have to be in heap order by frame already, e.g. all equal.
     fn new<F: FnMut(usize, &mut T)>(mut f: F) -> Self {
+        assert!(
+            Data::LEN == Queue::LEN,
+            "io_cache heap arrays differ in size"
+        );
         let mut data = Data::new();
         for (idx, elem) in data.get_mut().iter_mut().enumerate() {
             f(idx, &mut elem.data);
         }
         let mut queue = Queue::new();
-        for (idx, elem) in queue.get_mut().iter_mut().enumerate() {
-            *elem = idx;
-        }
+        heap_init(data.get_mut(), queue.get_mut());
         Self {
             data,
             queue,
@@ -191,114 +523,107 @@ impl<T: Clone + Default, Data: Array<HeapElem<T>>, Queue: Array<usize>> Heap<T,
         }
     }
 
-    /*
-    fn get_ref(&self, idx: usize) -> &T {
+    fn top(&self) -> usize {
+        self.queue.get_ref()[0]
+    }
+
+    fn get(&self, idx: usize) -> &T {
         &self.data.get_ref()[idx].data
     }
-    */
 
-    /*
-    fn get_mut(&mut self, idx: usize) -> &mut T {
-        &mut self.data.get_mut()[idx].data
+    fn update<F: FnOnce(&mut T), Cmp: Fn(&T, &T) -> i8>(&mut self, idx: usize, f: F, cmp: Cmp) {
+        f(&mut self.data.get_mut()[idx].data);
+        heap_fix(self.data.get_mut(), self.queue.get_mut(), idx, cmp);
     }
-    */
 
-    fn top(&self) -> usize {
-        self.queue.get_ref()[0]
+    fn update_all<F: FnMut(&mut T), Cmp: Fn(&T, &T) -> i8>(&mut self, f: F, cmp: Cmp) {
+        heap_update_all(self.data.get_mut(), self.queue.get_mut(), f, cmp);
     }
+}
 
-    fn update<F: FnOnce(&mut T), Cmp: Fn(&T, &T) -> i8>(&mut self, idx: usize, f: F, cmp: Cmp) {
-        let pos = {
-            let b = &mut self.data.get_mut()[idx];
-            f(&mut b.data);
-            b.pos
-        };
-        let left = ((pos + 1) * 2) - 1;
-        let right = (pos + 1) * 2;
-        if left < Queue::LEN {
-            if right < Queue::LEN {
-                let (left_idx, right_idx) = {
-                    let queue = self.queue.get_ref();
-                    (queue[left], queue[right])
-                };
-                self.send_down(idx, pos, left_idx, left, right_idx, right, cmp);
-            } else {
-                let left_idx = self.queue.get_ref()[left];
-                let flag = {
-                    let data = self.data.get_ref();
-                    cmp(&data[idx].data, &data[left_idx].data) >= 0
-                };
-                if flag {
-                    let queue = self.queue.get_mut();
-                    queue[pos] = queue[left];
-                    queue[left] = idx;
-                }
-            }
+// When `LFU` and `FALFU` halve all counts, so pages that used to be hot eventually make room.
+pub trait LFUAging {
+    fn new() -> Self;
+    // Called on every replacement and access, returns whether to halve the counts now.
+    fn tick(&mut self) -> bool;
+}
+
+pub struct NoAging;
+
+impl LFUAging for NoAging {
+    fn new() -> Self {
+        Self
+    }
+
+    fn tick(&mut self) -> bool {
+        false
+    }
+}
+
+// Halves the counts every `Ops` replacements and accesses.
+pub struct OpsAging<Ops: ConstUsize> {
+    ops: usize,
+    _phantom: std::marker::PhantomData<Ops>,
+}
+
+impl<Ops: ConstUsize> LFUAging for OpsAging<Ops> {
+    fn new() -> Self {
+        assert!(Ops::VALUE > 0, "io_cache OpsAging needs a period");
+        Self {
+            ops: 0,
+            _phantom: std::marker::PhantomData,
         }
     }
 
-    fn send_down<Cmp: Fn(&T, &T) -> i8>(
-        &mut self,
-        idx: usize,
-        mut pos: usize,
-        left_idx: usize,
-        mut left_pos: usize,
-        right_idx: usize,
-        mut right_pos: usize,
-        cmp: Cmp,
-    ) {
-        loop {
-            let flag = {
-                let data = self.data.get_ref();
-                if cmp(&data[idx].data, &data[left_idx].data) >= 0 {
-                    -1
-                } else if cmp(&data[idx].data, &data[right_idx].data) >= 0 {
-                    1
-                } else {
-                    0
-                }
-            };
+    fn tick(&mut self) -> bool {
+        self.ops += 1;
+        if self.ops < Ops::VALUE {
+            return false;
+        }
+        self.ops = 0;
+        true
+    }
+}
 
-            if flag < 0 {
-                let queue = self.queue.get_mut();
-                queue[pos] = queue[left_pos];
-                queue[left_pos] = idx;
-                pos = left_pos;
-            } else if flag > 0 {
-                let queue = self.queue.get_mut();
-                queue[pos] = queue[right_pos];
-                queue[right_pos] = idx;
-                pos = right_pos;
-            } else {
-                return;
-            }
+// Halves the counts once `Millis` milliseconds passed since the last time, checked on use.
+pub struct TimeAging<Millis: ConstUsize> {
+    last: std::time::Instant,
+    _phantom: std::marker::PhantomData<Millis>,
+}
 
-            left_pos = ((pos + 1) * 2) - 1;
-            right_pos = (pos + 1) * 2;
+impl<Millis: ConstUsize> LFUAging for TimeAging<Millis> {
+    fn new() -> Self {
+        Self {
+            last: std::time::Instant::now(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
 
-            if left_pos >= Queue::LEN {
-                return;
-            } else if right_pos >= Queue::LEN {
-                let flag = {
-                    let data = self.data.get_ref();
-                    cmp(&data[idx].data, &data[left_idx].data) >= 0
-                };
-                if flag {
-                    let queue = self.queue.get_mut();
-                    queue[pos] = queue[left_pos];
-                    queue[left_pos] = idx;
-                }
-                return;
-            }
+    fn tick(&mut self) -> bool {
+        let period = std::time::Duration::from_millis(Millis::VALUE as u64);
+        if self.last.elapsed() < period {
+            return false;
         }
+        self.last = std::time::Instant::now();
+        true
     }
 }
 
-pub struct LFU<Data: Array<HeapElem<u64>>, Queue: Array<usize>> {
-    heap: Heap<u64, Data, Queue>,
+pub struct LFU<Data: Array<HeapElem<LFUMeta>>, Queue: Array<usize>, Aging: LFUAging> {
+    heap: Heap<LFUMeta, Data, Queue>,
+    aging: Aging,
+    now: u64,
+}
+
+// Empty frames have a count of 0, below any cached page. Equal counts go by recency, the least
+// recently used page first.
+#[derive(Default, Clone, Copy)]
+pub struct LFUMeta {
+    count: u64,
+    time: u64,
 }
 
-fn lfu_cmp(l: &u64, r: &u64) -> i8 {
+fn cmp_u64(l: &u64, r: &u64) -> i8 {
     if *l < *r {
         -1
     } else {
@@ -306,42 +631,130 @@ fn lfu_cmp(l: &u64, r: &u64) -> i8 {
     }
 }
 
-impl<Data: Array<HeapElem<u64>>, Queue: Array<usize>> Replace for LFU<Data, Queue> {
+fn lfu_cmp(l: &LFUMeta, r: &LFUMeta) -> i8 {
+    match cmp_u64(&l.count, &r.count) {
+        0 => cmp_u64(&l.time, &r.time),
+        ord => ord,
+    }
+}
+
+fn lfu_halve(m: &mut LFUMeta) {
+    if m.count > 0 {
+        m.count = std::cmp::max(m.count / 2, 1);
+    }
+}
+
+impl<Data: Array<HeapElem<LFUMeta>>, Queue: Array<usize>, Aging: LFUAging> Replace
+    for LFU<Data, Queue, Aging>
+{
     const STATIC_META_MEM: usize =
         std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Data::LEN);
-    const META_MEM_PER_BLOCK: usize = Heap::<u64, Data, Queue>::META_MEM_PER_BLOCK;
+    const META_MEM_PER_BLOCK: usize = Heap::<LFUMeta, Data, Queue>::META_MEM_PER_BLOCK;
 
     fn new(_: usize) -> Self {
         Self {
             heap: Heap::new(|_, _| {}),
+            aging: Aging::new(),
+            now: 0,
         }
     }
 
-    fn replace(&mut self) -> usize {
+    fn replace(&mut self, _: u64) -> usize {
+        if self.aging.tick() {
+            self.heap.update_all(lfu_halve, lfu_cmp);
+        }
+        self.now += 1;
+        let now = self.now;
         let ret = self.heap.top();
-        self.heap.update(ret, |t| *t = 0, lfu_cmp);
+        self.heap.update(
+            ret,
+            |m| {
+                m.count = 1;
+                m.time = now;
+            },
+            lfu_cmp,
+        );
         ret
     }
 
     fn record_access(&mut self, idx: usize) {
-        self.heap.update(idx, |t| *t += 1, lfu_cmp);
+        if self.aging.tick() {
+            self.heap.update_all(lfu_halve, lfu_cmp);
+        }
+        self.now += 1;
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |m| {
+                m.count = m.count.saturating_add(1);
+                m.time = now;
+            },
+            lfu_cmp,
+        );
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |m| {
+                m.count = 1;
+                m.time = now;
+            },
+            lfu_cmp,
+        );
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.heap.update(idx, |m| *m = LFUMeta::default(), lfu_cmp);
     }
 }
 
+// `Rate` is the initial decay of the CRF per access, from 0 (LRU) to 1 (LFU), and can be changed
+// with `set_rate`, e.g. by `AdaptiveLRFU`.
 pub struct LRFU<Data: Array<HeapElem<LRFUMeta>>, Queue: Array<usize>, Rate: ConstF32> {
     heap: Heap<LRFUMeta, Data, Queue>,
     now: u64,
+    rate: f32,
     _phantom: std::marker::PhantomData<Rate>,
 }
 
+// Empty frames have a CRF of 0, below any cached page.
 #[derive(Default, Clone, Copy)]
 pub struct LRFUMeta {
     crf: f32,
     time: u64,
 }
 
-fn crf_calc<Rate: ConstF32>(b: &LRFUMeta, now: u64) -> f32 {
-    Rate::VALUE.powi((now - b.time) as i32) * b.crf
+pub trait LRFURate {
+    fn rate(&self) -> f32;
+    fn set_rate(&mut self, rate: f32);
+}
+
+fn lrfu_check_rate(rate: f32) {
+    assert!(
+        rate > 0.0 && rate <= 1.0,
+        "io_cache LRFU rate has to be in (0, 1]"
+    );
+}
+
+fn crf_calc(b: &LRFUMeta, now: u64, rate: f32) -> f32 {
+    ((now - b.time) as f64 * (rate as f64).ln()).exp() as f32 * b.crf
+}
+
+// CRFs all decay at the same rate, so they are compared as of time 0, in log space so that the
+// CRFs of long idle pages don't underflow.
+fn lrfu_cmp(rate: f32) -> impl Fn(&LRFUMeta, &LRFUMeta) -> i8 {
+    let ln_rate = (rate as f64).ln();
+    move |l, r| {
+        let key = |b: &LRFUMeta| (b.crf as f64).ln() - b.time as f64 * ln_rate;
+        let (l, r) = (key(l), key(r));
+        if l < r {
+            -1
+        } else {
+            (l > r) as i8
+        }
+    }
 }
 
 impl<Data: Array<HeapElem<LRFUMeta>>, Queue: Array<usize>, Rate: ConstF32> Replace
@@ -352,432 +765,3092 @@ impl<Data: Array<HeapElem<LRFUMeta>>, Queue: Array<usize>, Rate: ConstF32> Repla
     const META_MEM_PER_BLOCK: usize = Heap::<LRFUMeta, Data, Queue>::META_MEM_PER_BLOCK;
 
     fn new(_: usize) -> Self {
+        lrfu_check_rate(Rate::VALUE);
         Self {
-            heap: Heap::new(|_, b: &mut LRFUMeta| {
-                b.crf = 1.0;
-            }),
+            heap: Heap::new(|_, _| {}),
             now: 0,
+            rate: Rate::VALUE,
             _phantom: std::marker::PhantomData,
         }
     }
 
-    fn replace(&mut self) -> usize {
+    fn replace(&mut self, _: u64) -> usize {
         let ret = self.heap.top();
         let now = self.now;
         self.heap.update(
             ret,
             |b| {
                 b.crf = 1.0;
-                b.time = 0;
-            },
-            |_, r| {
-                let crf = crf_calc::<Rate>(r, now);
-                if crf < 1.0 {
-                    1
-                } else if crf > 1.0 {
-                    -1
-                } else {
-                    0
-                }
+                b.time = now;
             },
+            lrfu_cmp(self.rate),
         );
         ret
     }
 
     fn record_access(&mut self, idx: usize) {
         self.now += 1;
-        let now = self.now;
+        let (now, rate) = (self.now, self.rate);
         self.heap.update(
             idx,
             |b| {
-                b.crf = crf_calc::<Rate>(b, now) + 1.0;
+                b.crf = crf_calc(b, now, rate) + 1.0;
                 b.time = now;
             },
-            |l, r| {
-                let r_crf = crf_calc::<Rate>(r, now);
-                if r_crf < l.crf {
-                    1
-                } else if r_crf > l.crf {
-                    -1
-                } else {
-                    0
-                }
+            lrfu_cmp(rate),
+        );
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |b| {
+                b.crf = 1.0;
+                b.time = now;
             },
+            lrfu_cmp(self.rate),
         );
     }
-}
 
-pub struct FIFO<Size: ConstUsize> {
-    curr: usize,
-    _phantom: std::marker::PhantomData<Size>,
+    fn record_remove(&mut self, idx: usize) {
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |b| {
+                b.crf = 0.0;
+                b.time = now;
+            },
+            lrfu_cmp(self.rate),
+        );
+    }
 }
 
-impl<Size: ConstUsize> Replace for FIFO<Size> {
-    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = 0;
+impl<Data: Array<HeapElem<LRFUMeta>>, Queue: Array<usize>, Rate: ConstF32> LRFURate
+    for LRFU<Data, Queue, Rate>
+{
+    fn rate(&self) -> f32 {
+        self.rate
+    }
 
-    fn new(_: usize) -> Self {
-        Self {
-            curr: 0,
-            _phantom: std::marker::PhantomData,
-        }
+    // The order of pages accessed at different times changes with the rate.
+    fn set_rate(&mut self, rate: f32) {
+        lrfu_check_rate(rate);
+        self.rate = rate;
+        self.heap.update_all(|_| {}, lrfu_cmp(rate));
     }
+}
 
-    fn replace(&mut self) -> usize {
-        let ret = self.curr;
-        self.curr = (self.curr + 1) & (Size::VALUE - 1);
-        ret
+// Tunes the rate of an `LRFU` or `FALRFU` from ghost hits, pages missed again shortly after being
+// evicted: ones that were accessed while cached call for more weight on frequency, ones that
+// weren't for more weight on recency. The rate moves in steps of `LAMBDA_STEP` in log space.
+pub struct AdaptiveLRFU<R: Replace + LRFURate> {
+    inner: R,
+    pages: Vec<u64>,
+    accessed: Vec<bool>,
+    // Evicted page to whether it was accessed and its eviction number, oldest in `order` first.
+    ghosts: std::collections::HashMap<u64, (bool, u64)>,
+    order: std::collections::VecDeque<(u64, u64)>,
+    evictions: u64,
+}
+
+const MIN_LAMBDA: f64 = 1e-4;
+const MAX_LAMBDA: f64 = 4.0;
+const LAMBDA_STEP: f64 = 1.5;
+
+impl<R: Replace + LRFURate> AdaptiveLRFU<R> {
+    pub fn rate(&self) -> f32 {
+        self.inner.rate()
     }
 
-    fn record_access(&mut self, _: usize) {}
-}
+    fn adapt(&mut self, accessed: bool) {
+        let lambda = -(self.inner.rate() as f64).ln();
+        let lambda = if accessed {
+            lambda / LAMBDA_STEP
+        } else {
+            lambda * LAMBDA_STEP
+        };
+        let lambda = lambda.clamp(MIN_LAMBDA, MAX_LAMBDA);
+        self.inner.set_rate((-lambda).exp() as f32);
+    }
 
-pub struct FARandom {
-    seed: usize,
-    count: usize,
+    fn remember(&mut self, page: u64, accessed: bool) {
+        self.evictions += 1;
+        self.ghosts.insert(page, (accessed, self.evictions));
+        self.order.push_back((page, self.evictions));
+        while self.order.len() > self.pages.len() {
+            let (page, eviction) = self.order.pop_front().unwrap();
+            if self.ghosts.get(&page).map(|g| g.1) == Some(eviction) {
+                self.ghosts.remove(&page);
+            }
+        }
+    }
 }
 
-impl Replace for FARandom {
-    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = 0;
+impl<R: Replace + LRFURate> Replace for AdaptiveLRFU<R> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - std::mem::size_of::<R>() + R::STATIC_META_MEM;
+    const META_MEM_PER_BLOCK: usize = R::META_MEM_PER_BLOCK
+        + std::mem::size_of::<u64>()
+        + std::mem::size_of::<bool>()
+        + std::mem::size_of::<(u64, (bool, u64))>()
+        + std::mem::size_of::<(u64, u64)>();
 
     fn new(count: usize) -> Self {
         Self {
-            seed: 0x981234,
-            count,
+            inner: R::new(count),
+            pages: vec![NIL; count],
+            accessed: vec![false; count],
+            ghosts: std::collections::HashMap::with_capacity(count),
+            order: std::collections::VecDeque::with_capacity(count),
+            evictions: 0,
         }
     }
 
-    fn replace(&mut self) -> usize {
-        let s = self.seed;
-        if std::mem::size_of::<usize>() == std::mem::size_of::<u32>() {
-            let s = s ^ (s << 13);
-            let s = s ^ (s >> 17);
-            let s = s ^ (s << 5);
-            self.seed = s;
-            s % self.count
-        } else if std::mem::size_of::<usize>() == std::mem::size_of::<u64>() {
-            let s = s ^ (s << 13);
-            let s = s ^ (s >> 7);
-            let s = s ^ (s << 17);
-            self.seed = s;
-            s % self.count
-        } else {
-            panic!("io_cache random replacement not supported on this architecture");
+    fn replace(&mut self, page: u64) -> usize {
+        self.inner.replace(page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.accessed[idx] = true;
+        self.inner.record_access(idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        if let Some((accessed, _)) = self.ghosts.remove(&page) {
+            self.adapt(accessed);
         }
+        if self.pages[idx] != NIL {
+            self.remember(self.pages[idx], self.accessed[idx]);
+        }
+        self.pages[idx] = page;
+        self.accessed[idx] = false;
+        self.inner.record_insert(idx, page);
     }
 
-    fn record_access(&mut self, _: usize) {}
+    fn record_remove(&mut self, idx: usize) {
+        self.pages[idx] = NIL;
+        self.inner.record_remove(idx);
+    }
 }
 
-pub struct FALRU {
-    list: Vec<LRUMeta>,
-    front: usize,
-    back: usize,
+// GreedyDual: every page is worth the inflation at its last access plus its miss cost, and the
+// page worth least is evicted, raising the inflation to its worth. Cheap pages go first, but
+// expensive ones that stop being accessed are eventually overtaken by the inflation. Pages get a
+// cost of 1 until `record_cost` says otherwise, which makes it LRU when costs are unknown.
+pub struct GreedyDual<Data: Array<HeapElem<GDMeta>>, Queue: Array<usize>> {
+    heap: Heap<GDMeta, Data, Queue>,
+    inflation: u64,
 }
 
-impl Replace for FALRU {
-    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<LRUMeta>();
+// Empty frames are worth 0, below any cached page.
+#[derive(Default, Clone, Copy)]
+pub struct GDMeta {
+    worth: u64,
+    cost: u64,
+}
 
-    fn new(count: usize) -> Self {
-        let cm1 = count - 1;
-        let mut meta = vec![LRUMeta::default(); count];
-        for (idx, block) in meta.iter_mut().enumerate() {
-            if idx == 0 {
-                block.prev = NULL;
-            } else {
-                block.prev = idx - 1;
-            }
-            if idx == cm1 {
-                block.next = NULL;
-            } else {
-                block.next = idx + 1;
-            }
-        }
+const GD_MIN_COST: u64 = 1;
+
+fn gd_cmp(l: &GDMeta, r: &GDMeta) -> i8 {
+    cmp_u64(&l.worth, &r.worth)
+}
+
+fn gd_touch(inflation: u64) -> impl FnOnce(&mut GDMeta) {
+    move |m| m.worth = inflation.saturating_add(m.cost)
+}
+
+fn gd_insert(inflation: u64, cost: u64) -> impl FnOnce(&mut GDMeta) {
+    move |m| {
+        m.cost = std::cmp::max(cost, GD_MIN_COST);
+        m.worth = inflation.saturating_add(m.cost);
+    }
+}
+
+impl<Data: Array<HeapElem<GDMeta>>, Queue: Array<usize>> Replace for GreedyDual<Data, Queue> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Data::LEN);
+    const META_MEM_PER_BLOCK: usize = Heap::<GDMeta, Data, Queue>::META_MEM_PER_BLOCK;
+    const COST_AWARE: bool = true;
+
+    fn new(_: usize) -> Self {
         Self {
-            list: meta,
-            front: 0,
-            back: cm1,
+            heap: Heap::new(|_, _| {}),
+            inflation: 0,
         }
     }
 
-    fn replace(&mut self) -> usize {
-        let ret = self.front;
-        let next = self.list[ret].next;
-        if next != NULL {
-            self.front = next;
-            let back = self.back;
-            self.back = ret;
-            {
-                self.list[back].next = ret;
-                let r = &mut self.list[ret];
-                r.prev = back;
-                r.next = NULL;
-            }
-        }
+    fn replace(&mut self, _: u64) -> usize {
+        let ret = self.heap.top();
+        self.inflation = std::cmp::max(self.inflation, self.heap.get(ret).worth);
+        self.heap
+            .update(ret, gd_insert(self.inflation, GD_MIN_COST), gd_cmp);
         ret
     }
 
-    fn record_access(&mut self, block_idx: usize) {
-        let back = self.back;
-        if back != block_idx {
-            let front = self.front;
-            if front == block_idx {
-                let next = self.list[front].next;
-                self.front = next;
-                self.back = front;
-                {
-                    self.list[next].prev = NULL;
-                    self.list[back].next = front;
-                    let b = &mut self.list[front];
-                    b.prev = back;
-                    b.next = NULL;
-                }
-            } else {
-                self.back = block_idx;
-                let (next, prev) = {
-                    let data = &self.list[block_idx];
-                    (data.next, data.prev)
-                };
-                {
-                    self.list[next].prev = prev;
-                    self.list[prev].next = next;
-                    self.list[block_idx].prev = back;
-                    self.list[block_idx].next = NULL;
-                    self.list[back].next = block_idx;
-                }
-            }
-        }
+    fn record_access(&mut self, idx: usize) {
+        self.heap.update(idx, gd_touch(self.inflation), gd_cmp);
     }
-}
 
-#[derive(Default, Clone, Copy)]
-struct FAHeapElem<T> {
-    data: T,
-    pos: usize,
+    fn record_cost(&mut self, idx: usize, cost: u64) {
+        self.heap
+            .update(idx, gd_insert(self.inflation, cost), gd_cmp);
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.heap
+            .update(idx, gd_insert(self.inflation, GD_MIN_COST), gd_cmp);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.heap.update(idx, |m| *m = GDMeta::default(), gd_cmp);
+    }
 }
 
-struct FAHeap<T: Default + Clone> {
-    data: Vec<FAHeapElem<T>>,
-    queue: Vec<usize>,
+pub struct FIFO<Size: ConstUsize> {
+    curr: usize,
+    free: FreeFrames,
+    _phantom: std::marker::PhantomData<Size>,
 }
 
-impl<T: Default + Clone> FAHeap<T> {
-    // const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize =
-        std::mem::size_of::<HeapElem<T>>() + std::mem::size_of::<usize>();
+impl<Size: ConstUsize> Replace for FIFO<Size> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>() + FreeFrames::mem(Size::VALUE);
+    const META_MEM_PER_BLOCK: usize = 0;
 
-    fn new<F: FnMut(usize, &mut T)>(count: usize, mut f: F) -> Self {
-        let mut data: Vec<FAHeapElem<T>> = vec![FAHeapElem::default(); count];
-        for (idx, elem) in data.iter_mut().enumerate() {
-            f(idx, &mut elem.data);
+    fn new(_: usize) -> Self {
+        Self {
+            curr: 0,
+            free: FreeFrames::empty(Size::VALUE),
+            _phantom: std::marker::PhantomData,
         }
-        let mut queue = vec![0; count];
-        for (idx, elem) in queue.iter_mut().enumerate() {
-            *elem = idx;
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        if let Some(frame) = self.free.pop() {
+            return frame;
         }
-        Self { data, queue }
+        let ret = self.curr;
+        self.curr = (self.curr + 1) % Size::VALUE;
+        ret
     }
 
-    /*
-    fn get_ref(&self, idx: usize) -> &T {
-        &self.data[idx].data
+    fn record_access(&mut self, _: usize) {}
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.free.take(idx);
     }
-    */
 
-    /*
-    fn get_mut(&mut self, idx: usize) -> &mut T {
-        &mut self.data[idx].data
+    fn record_remove(&mut self, idx: usize) {
+        self.free.put(idx);
     }
-    */
+}
 
-    fn top(&self) -> usize {
-        self.queue[0]
+pub struct Clock<Size: ConstUsize, Bits: Array<u64>> {
+    bits: Bits,
+    hand: usize,
+    free: FreeFrames,
+    _phantom: std::marker::PhantomData<Size>,
+}
+
+impl<Size: ConstUsize, Bits: Array<u64>> Replace for Clock<Size, Bits> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>() + FreeFrames::mem(Size::VALUE);
+    const META_MEM_PER_BLOCK: usize = 0;
+
+    fn new(_: usize) -> Self {
+        assert!(
+            Bits::LEN * 64 >= Size::VALUE,
+            "io_cache clock bitmap too small for the set"
+        );
+        Self {
+            bits: Bits::new_with(0),
+            hand: 0,
+            free: FreeFrames::empty(Size::VALUE),
+            _phantom: std::marker::PhantomData,
+        }
     }
 
-    fn update<F: FnOnce(&mut T), Cmp: Fn(&T, &T) -> i8>(&mut self, idx: usize, f: F, cmp: Cmp) {
-        let pos = {
-            let b = &mut self.data[idx];
-            f(&mut b.data);
-            b.pos
-        };
-        let left = ((pos + 1) * 2) - 1;
-        let right = (pos + 1) * 2;
-        if left < self.queue.len() {
-            if right < self.queue.len() {
-                let (left_idx, right_idx) = { (self.queue[left], self.queue[right]) };
-                self.send_down(idx, pos, left_idx, left, right_idx, right, cmp);
-            } else {
-                let left_idx = self.queue[left];
-                let flag = { cmp(&self.data[idx].data, &self.data[left_idx].data) >= 0 };
-                if flag {
-                    self.queue[pos] = self.queue[left];
-                    self.queue[left] = idx;
-                }
+    fn replace(&mut self, _: u64) -> usize {
+        if let Some(frame) = self.free.pop() {
+            return frame;
+        }
+        let bits = self.bits.get_mut();
+        loop {
+            let idx = self.hand;
+            self.hand = (self.hand + 1) % Size::VALUE;
+            let mask = 1 << (idx % 64);
+            if bits[idx / 64] & mask == 0 {
+                return idx;
             }
+            bits[idx / 64] &= !mask;
         }
     }
 
-    fn send_down<Cmp: Fn(&T, &T) -> i8>(
-        &mut self,
-        idx: usize,
-        mut pos: usize,
-        left_idx: usize,
-        mut left_pos: usize,
-        right_idx: usize,
-        mut right_pos: usize,
-        cmp: Cmp,
-    ) {
-        loop {
-            let flag = {
-                if cmp(&self.data[idx].data, &self.data[left_idx].data) >= 0 {
-                    -1
-                } else if cmp(&self.data[idx].data, &self.data[right_idx].data) >= 0 {
-                    1
-                } else {
-                    0
-                }
-            };
+    fn record_access(&mut self, idx: usize) {
+        self.bits.get_mut()[idx / 64] |= 1 << (idx % 64);
+    }
 
-            if flag < 0 {
-                self.queue[pos] = self.queue[left_pos];
-                self.queue[left_pos] = idx;
-                pos = left_pos;
-            } else if flag > 0 {
-                self.queue[pos] = self.queue[right_pos];
-                self.queue[right_pos] = idx;
-                pos = right_pos;
-            } else {
-                return;
-            }
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.free.take(idx);
+    }
 
-            left_pos = ((pos + 1) * 2) - 1;
-            right_pos = (pos + 1) * 2;
+    fn record_remove(&mut self, idx: usize) {
+        self.bits.get_mut()[idx / 64] &= !(1 << (idx % 64));
+        self.free.put(idx);
+    }
+}
 
-            if left_pos >= self.queue.len() {
-                return;
-            } else if right_pos >= self.queue.len() {
-                let flag = { cmp(&self.data[idx].data, &self.data[left_idx].data) >= 0 };
-                if flag {
-                    self.queue[pos] = self.queue[left_pos];
-                    self.queue[left_pos] = idx;
-                }
-                return;
+// Binary tree of `Size::VALUE - 1` bits, node `n` having children `2n` and `2n + 1`. Each bit
+// points at the half of its subtree holding the next victim and is flipped away from a way
+// whenever that way is accessed.
+pub struct TreePLRU<Size: ConstUsize> {
+    bits: u64,
+    free: u64,
+    _phantom: std::marker::PhantomData<Size>,
+}
+
+impl<Size: ConstUsize> TreePLRU<Size> {
+    const LEVELS: u32 = Size::VALUE.trailing_zeros();
+
+    fn touch(&mut self, way: usize) {
+        let mut node = 1;
+        for level in (0..Self::LEVELS).rev() {
+            let right = (way >> level) & 1;
+            if right == 1 {
+                self.bits &= !(1 << node);
+            } else {
+                self.bits |= 1 << node;
             }
+            node = node * 2 + right;
         }
     }
 }
 
-pub struct FALFU {
-    heap: FAHeap<u64>,
-}
-
-impl Replace for FALFU {
+impl<Size: ConstUsize> Replace for TreePLRU<Size> {
     const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = FAHeap::<u64>::META_MEM_PER_BLOCK;
+    const META_MEM_PER_BLOCK: usize = 0;
 
-    fn new(count: usize) -> Self {
+    fn new(_: usize) -> Self {
+        assert!(
+            Size::VALUE.is_power_of_two() && Size::VALUE <= 64,
+            "io_cache tree PLRU needs a power of two of at most 64 ways"
+        );
         Self {
-            heap: FAHeap::new(count, |_, _| {}),
+            bits: 0,
+            free: 0,
+            _phantom: std::marker::PhantomData,
         }
     }
 
-    fn replace(&mut self) -> usize {
-        let ret = self.heap.top();
-        self.heap.update(ret, |t| *t = 0, lfu_cmp);
-        ret
+    fn replace(&mut self, _: u64) -> usize {
+        if let Some(way) = pop_free(&mut self.free) {
+            self.touch(way);
+            return way;
+        }
+        let mut node = 1;
+        for _ in 0..Self::LEVELS {
+            node = node * 2 + ((self.bits >> node) & 1) as usize;
+        }
+        let way = node - Size::VALUE;
+        self.touch(way);
+        way
     }
 
     fn record_access(&mut self, idx: usize) {
-        self.heap.update(idx, |t| *t += 1, lfu_cmp);
+        self.touch(idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        if take_free(&mut self.free, idx) {
+            self.touch(idx);
+        }
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.free |= 1 << idx;
     }
 }
 
-pub struct FALRFU<Rate: ConstF32> {
-    heap: FAHeap<LRFUMeta>,
-    now: u64,
-    _marker: std::marker::PhantomData<Rate>,
+// One MRU bit per way. Accessing a way sets its bit, clearing all others once every bit is set;
+// the victim is the first way with its bit clear.
+pub struct BitPLRU<Size: ConstUsize> {
+    bits: u64,
+    free: u64,
+    _phantom: std::marker::PhantomData<Size>,
 }
 
-impl<Rate: ConstF32> Replace for FALRFU<Rate> {
+pub type NRU<Size> = BitPLRU<Size>;
+
+impl<Size: ConstUsize> BitPLRU<Size> {
+    const ALL: u64 = u64::MAX >> (64 - Size::VALUE);
+
+    fn touch(&mut self, way: usize) {
+        self.bits |= 1 << way;
+        if self.bits == Self::ALL {
+            self.bits = 1 << way;
+        }
+    }
+}
+
+impl<Size: ConstUsize> Replace for BitPLRU<Size> {
     const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = FAHeap::<u64>::META_MEM_PER_BLOCK;
+    const META_MEM_PER_BLOCK: usize = 0;
 
-    fn new(count: usize) -> Self {
+    fn new(_: usize) -> Self {
+        assert!(
+            Size::VALUE >= 2 && Size::VALUE <= 64,
+            "io_cache bit PLRU needs between 2 and 64 ways"
+        );
         Self {
-            heap: FAHeap::new(count, |_, b: &mut LRFUMeta| {
-                b.crf = 1.0;
-            }),
-            now: 0,
-            _marker: std::marker::PhantomData,
+            bits: 0,
+            free: 0,
+            _phantom: std::marker::PhantomData,
         }
     }
 
-    fn replace(&mut self) -> usize {
-        let ret = self.heap.top();
-        let now = self.now;
-        self.heap.update(
-            ret,
-            |b| {
-                b.crf = 1.0;
-                b.time = 0;
-            },
-            |_, r| {
-                let crf = crf_calc::<Rate>(r, now);
-                if crf < 1.0 {
-                    1
-                } else if crf > 1.0 {
-                    -1
-                } else {
-                    0
-                }
-            },
-        );
-        ret
+    fn replace(&mut self, _: u64) -> usize {
+        let way = match pop_free(&mut self.free) {
+            Some(way) => way,
+            None => self.bits.trailing_ones() as usize,
+        };
+        self.touch(way);
+        way
     }
 
     fn record_access(&mut self, idx: usize) {
-        self.now += 1;
-        let now = self.now;
-        self.heap.update(
-            idx,
-            |b| {
-                b.crf = crf_calc::<Rate>(b, now) + 1.0;
-                b.time = now;
-            },
-            |l, r| {
-                let r_crf = crf_calc::<Rate>(r, now);
-                if r_crf < l.crf {
-                    1
-                } else if r_crf > l.crf {
-                    -1
-                } else {
-                    0
-                }
-            },
-        );
+        self.touch(idx);
     }
-}
 
-pub struct FAFIFO {
-    curr: usize,
-    count: usize,
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        if take_free(&mut self.free, idx) {
+            self.touch(idx);
+        }
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.bits &= !(1 << idx);
+        self.free |= 1 << idx;
+    }
 }
 
-impl Replace for FAFIFO {
-    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = 0;
+const PSEL_MAX: usize = 1023;
+const DUEL_SPACING: usize = 32;
 
-    fn new(count: usize) -> Self {
-        Self { curr: 0, count }
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DuelRole {
+    LeaderA,
+    LeaderB,
+    Follower,
+}
+
+// Set dueling: one set out of every `DUEL_SPACING` always uses policy A and the next one policy
+// B. Misses in those leader sets move a saturating selector shared by all sets of the cache, and
+// the other sets follow whichever policy is missing less.
+pub struct SetDuel {
+    psel: std::sync::atomic::AtomicUsize,
+}
+
+impl Default for SetDuel {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn replace(&mut self) -> usize {
-        let ret = self.curr;
-        self.curr = (self.curr + 1) % self.count;
+impl SetDuel {
+    pub fn new() -> Self {
+        Self {
+            psel: std::sync::atomic::AtomicUsize::new(PSEL_MAX / 2 + 1),
+        }
+    }
+
+    pub fn role(set: usize, sets: usize) -> DuelRole {
+        match set % DUEL_SPACING {
+            _ if sets < 2 => DuelRole::Follower,
+            0 => DuelRole::LeaderA,
+            1 => DuelRole::LeaderB,
+            _ => DuelRole::Follower,
+        }
+    }
+
+    pub fn selector(&self) -> usize {
+        self.psel.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn record_miss(&self, role: DuelRole) {
+        let step = |psel: usize| match role {
+            DuelRole::LeaderA if psel < PSEL_MAX => Some(psel + 1),
+            DuelRole::LeaderB if psel > 0 => Some(psel - 1),
+            _ => None,
+        };
+        let _ = self.psel.fetch_update(
+            std::sync::atomic::Ordering::Relaxed,
+            std::sync::atomic::Ordering::Relaxed,
+            step,
+        );
+    }
+
+    pub fn prefers_b(&self) -> bool {
+        self.selector() > PSEL_MAX / 2 + 1
+    }
+}
+
+// A set's place in the duel. Outside of `NWaySets` there is no duel and A is always used.
+struct DuelSeat {
+    role: DuelRole,
+    duel: Option<std::sync::Arc<SetDuel>>,
+}
+
+impl DuelSeat {
+    fn new() -> Self {
+        Self {
+            role: DuelRole::Follower,
+            duel: None,
+        }
+    }
+
+    fn join(&mut self, set: usize, sets: usize, duel: &std::sync::Arc<SetDuel>) {
+        self.role = SetDuel::role(set, sets);
+        self.duel = Some(duel.clone());
+    }
+
+    // Which policy is in use.
+    fn uses_b(&self) -> bool {
+        match (&self.duel, self.role) {
+            (Some(duel), DuelRole::Follower) => duel.prefers_b(),
+            (Some(_), role) => role == DuelRole::LeaderB,
+            (None, _) => false,
+        }
+    }
+
+    // Which policy handles a miss, counting it if this is a leader set.
+    fn miss_uses_b(&self) -> bool {
+        if let (Some(duel), DuelRole::LeaderA | DuelRole::LeaderB) = (&self.duel, self.role) {
+            duel.record_miss(self.role);
+        }
+        self.uses_b()
+    }
+}
+
+// Re-reference interval prediction (Jaleel et al.): every way has an M-bit re-reference
+// prediction value, zeroed on a hit. Victims are ways predicted to be re-referenced in the distant
+// future, i.e. at the maximum value, aging all ways until one is.
+fn rrip_victim(rrpv: &mut [u8], max: u8) -> usize {
+    let oldest = rrpv.iter().copied().max().unwrap_or(max);
+    if oldest < max {
+        for v in rrpv.iter_mut() {
+            *v += max - oldest;
+        }
+    }
+    rrpv.iter().position(|v| *v == max).unwrap_or(0)
+}
+
+fn rrip_max<Bits: ConstUsize>() -> u8 {
+    assert!(
+        Bits::VALUE >= 1 && Bits::VALUE <= 8,
+        "io_cache RRIP needs between 1 and 8 bits per way"
+    );
+    ((1u16 << Bits::VALUE) - 1) as u8
+}
+
+// BRRIP inserts one page out of `BRRIP_EPSILON` with a long re-reference interval, the others
+// with a distant one.
+const BRRIP_EPSILON: u32 = 32;
+
+fn brrip_insert(inserts: &mut u32, max: u8) -> u8 {
+    *inserts = inserts.wrapping_add(1);
+    if inserts.is_multiple_of(BRRIP_EPSILON) {
+        max.saturating_sub(1)
+    } else {
+        max
+    }
+}
+
+// Static RRIP: new pages are predicted a long re-reference interval, so a scan of pages never
+// reused is evicted before pages that were hit.
+pub struct SRRIP<RRPV: Array<u8>, Bits: ConstUsize> {
+    rrpv: RRPV,
+    max: u8,
+    free: FreeFrames,
+    _phantom: std::marker::PhantomData<Bits>,
+}
+
+impl<RRPV: Array<u8>, Bits: ConstUsize> Replace for SRRIP<RRPV, Bits> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - RRPV::LEN + FreeFrames::mem(RRPV::LEN);
+    const META_MEM_PER_BLOCK: usize = 1;
+
+    fn new(_: usize) -> Self {
+        let max = rrip_max::<Bits>();
+        Self {
+            rrpv: RRPV::new_with(max),
+            max,
+            free: FreeFrames::full(RRPV::LEN),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        let rrpv = self.rrpv.get_mut();
+        let way = match self.free.pop() {
+            Some(way) => way,
+            None => rrip_victim(rrpv, self.max),
+        };
+        rrpv[way] = self.max - 1;
+        way
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.rrpv.get_mut()[idx] = 0;
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        if self.free.take(idx) {
+            self.rrpv.get_mut()[idx] = self.max - 1;
+        }
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.rrpv.get_mut()[idx] = self.max;
+        self.free.put(idx);
+    }
+}
+
+// Bimodal RRIP: new pages are mostly predicted a distant re-reference interval, so a working set
+// larger than the set keeps part of it resident instead of thrashing.
+pub struct BRRIP<RRPV: Array<u8>, Bits: ConstUsize> {
+    rrpv: RRPV,
+    max: u8,
+    free: FreeFrames,
+    inserts: u32,
+    _phantom: std::marker::PhantomData<Bits>,
+}
+
+impl<RRPV: Array<u8>, Bits: ConstUsize> Replace for BRRIP<RRPV, Bits> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - RRPV::LEN + FreeFrames::mem(RRPV::LEN);
+    const META_MEM_PER_BLOCK: usize = 1;
+
+    fn new(_: usize) -> Self {
+        let max = rrip_max::<Bits>();
+        Self {
+            rrpv: RRPV::new_with(max),
+            max,
+            free: FreeFrames::full(RRPV::LEN),
+            inserts: 0,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        let rrpv = self.rrpv.get_mut();
+        let way = match self.free.pop() {
+            Some(way) => way,
+            None => rrip_victim(rrpv, self.max),
+        };
+        rrpv[way] = brrip_insert(&mut self.inserts, self.max);
+        way
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.rrpv.get_mut()[idx] = 0;
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        if self.free.take(idx) {
+            self.rrpv.get_mut()[idx] = brrip_insert(&mut self.inserts, self.max);
+        }
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.rrpv.get_mut()[idx] = self.max;
+        self.free.put(idx);
+    }
+}
+
+// Dynamic RRIP: leader sets duel SRRIP (A) against BRRIP (B) and the others insert like the
+// winner. Outside of `NWaySets` there is no duel and it behaves like SRRIP.
+pub struct DRRIP<RRPV: Array<u8>, Bits: ConstUsize> {
+    rrpv: RRPV,
+    max: u8,
+    free: FreeFrames,
+    inserts: u32,
+    seat: DuelSeat,
+    _phantom: std::marker::PhantomData<Bits>,
+}
+
+impl<RRPV: Array<u8>, Bits: ConstUsize> Replace for DRRIP<RRPV, Bits> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - RRPV::LEN + FreeFrames::mem(RRPV::LEN);
+    const META_MEM_PER_BLOCK: usize = 1;
+
+    fn new(_: usize) -> Self {
+        let max = rrip_max::<Bits>();
+        Self {
+            rrpv: RRPV::new_with(max),
+            max,
+            free: FreeFrames::full(RRPV::LEN),
+            inserts: 0,
+            seat: DuelSeat::new(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        let bimodal = self.seat.miss_uses_b();
+        let rrpv = self.rrpv.get_mut();
+        let way = match self.free.pop() {
+            Some(way) => way,
+            None => rrip_victim(rrpv, self.max),
+        };
+        rrpv[way] = if bimodal {
+            brrip_insert(&mut self.inserts, self.max)
+        } else {
+            self.max - 1
+        };
+        way
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.rrpv.get_mut()[idx] = 0;
+    }
+
+    fn join_duel(&mut self, set: usize, sets: usize, duel: &std::sync::Arc<SetDuel>) {
+        self.seat.join(set, sets, duel);
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        if self.free.take(idx) {
+            self.rrpv.get_mut()[idx] = if self.seat.uses_b() {
+                brrip_insert(&mut self.inserts, self.max)
+            } else {
+                self.max - 1
+            };
+        }
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.rrpv.get_mut()[idx] = self.max;
+        self.free.put(idx);
+    }
+}
+
+// Duels two arbitrary policies across `NWaySets`. Every set keeps the state of both so followers
+// can switch whenever the selector does; to the policy not picking the victim, the frame is
+// emptied and filled again once the new page is inserted.
+pub struct Duel<A: Replace, B: Replace> {
+    a: A,
+    b: B,
+    seat: DuelSeat,
+    last_b: bool,
+    // The frame last returned by `replace`, until it is filled.
+    pending: usize,
+}
+
+impl<A: Replace, B: Replace> Replace for Duel<A, B> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - std::mem::size_of::<A>() - std::mem::size_of::<B>()
+            + A::STATIC_META_MEM
+            + B::STATIC_META_MEM;
+    const META_MEM_PER_BLOCK: usize = A::META_MEM_PER_BLOCK + B::META_MEM_PER_BLOCK;
+    const COST_AWARE: bool = A::COST_AWARE || B::COST_AWARE;
+
+    fn new(count: usize) -> Self {
+        Self {
+            a: A::new(count),
+            b: B::new(count),
+            seat: DuelSeat::new(),
+            last_b: false,
+            pending: NULL,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.last_b = self.seat.miss_uses_b();
+        self.pending = if self.last_b {
+            self.b.replace(page)
+        } else {
+            self.a.replace(page)
+        };
+        self.pending
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.a.record_access(idx);
+        self.b.record_access(idx);
+    }
+
+    fn admit(&mut self, page: u64, victim: usize) -> bool {
+        if self.last_b {
+            self.b.admit(page, victim)
+        } else {
+            self.a.admit(page, victim)
+        }
+    }
+
+    fn join_duel(&mut self, set: usize, sets: usize, duel: &std::sync::Arc<SetDuel>) {
+        self.seat.join(set, sets, duel);
+    }
+
+    fn join_set(&mut self, set: usize, sets: usize) {
+        self.a.join_set(set, sets);
+        self.b.join_set(set, sets);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        if self.pending == idx {
+            self.pending = NULL;
+            if self.last_b {
+                self.a.record_remove(idx);
+            } else {
+                self.b.record_remove(idx);
+            }
+        }
+        self.a.record_insert(idx, page);
+        self.b.record_insert(idx, page);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        if self.pending == idx {
+            self.pending = NULL;
+        }
+        self.a.record_remove(idx);
+        self.b.record_remove(idx);
+    }
+
+    fn record_dirty(&mut self, idx: usize, dirty: bool) {
+        self.a.record_dirty(idx, dirty);
+        self.b.record_dirty(idx, dirty);
+    }
+
+    fn record_cost(&mut self, idx: usize, cost: u64) {
+        self.a.record_cost(idx, cost);
+        self.b.record_cost(idx, cost);
+    }
+}
+
+// Intrusive doubly linked lists over a fixed node array, shared by the queue based policies
+// below. Every node is on exactly one list: `FREE` holds unused frames, `GHOST_FREE` unused ghost
+// entries and the lists in between are up to each policy.
+const FREE: u8 = 0;
+const GHOST_FREE: u8 = 5;
+// Stands for no list at all, e.g. for a page without a ghost entry.
+const NO_LIST: u8 = u8::MAX;
+
+#[derive(Clone, Copy)]
+pub struct ListMeta {
+    page: u64,
+    prev: usize,
+    next: usize,
+    list: u8,
+    freq: u8,
+}
+
+impl Default for ListMeta {
+    fn default() -> Self {
+        Self {
+            page: u64::MAX,
+            prev: NULL,
+            next: NULL,
+            list: FREE,
+            freq: 0,
+        }
+    }
+}
+
+pub type ARCMeta = ListMeta;
+pub type SLRUMeta = ListMeta;
+pub type TwoQMeta = ListMeta;
+pub type S3FIFOMeta = ListMeta;
+
+#[derive(Clone, Copy)]
+struct ListHead {
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+trait Ghosts {
+    fn find(&self, nodes: &[ListMeta], page: u64) -> usize;
+    fn insert(&mut self, page: u64, idx: usize);
+    fn remove(&mut self, page: u64);
+}
+
+// Ghost lookup for the fixed size policies, scanning the nodes from the first ghost entry on.
+struct GhostScan(usize);
+
+impl Ghosts for GhostScan {
+    fn find(&self, nodes: &[ListMeta], page: u64) -> usize {
+        for (idx, node) in nodes[self.0..].iter().enumerate() {
+            if node.page == page && node.list != GHOST_FREE {
+                return self.0 + idx;
+            }
+        }
+        NULL
+    }
+
+    fn insert(&mut self, _: u64, _: usize) {}
+    fn remove(&mut self, _: u64) {}
+}
+
+impl Ghosts for std::collections::HashMap<u64, usize> {
+    fn find(&self, _: &[ListMeta], page: u64) -> usize {
+        match self.get(&page) {
+            Some(idx) => *idx,
+            None => NULL,
+        }
+    }
+
+    fn insert(&mut self, page: u64, idx: usize) {
+        std::collections::HashMap::insert(self, page, idx);
+    }
+
+    fn remove(&mut self, page: u64) {
+        std::collections::HashMap::remove(self, &page);
+    }
+}
+
+struct Lists([ListHead; 6]);
+
+impl Lists {
+    // Nodes `0..frames` are the frames, the rest ghost entries.
+    fn new(nodes: &mut [ListMeta], frames: usize) -> Self {
+        let mut ret = Self(
+            [ListHead {
+                front: NULL,
+                back: NULL,
+                len: 0,
+            }; 6],
+        );
+        for idx in 0..nodes.len() {
+            nodes[idx] = ListMeta::default();
+            let list = if idx < frames { FREE } else { GHOST_FREE };
+            ret.push(nodes, list, idx);
+        }
         ret
     }
 
-    fn record_access(&mut self, _: usize) {}
+    fn len(&self, list: u8) -> usize {
+        self.0[list as usize].len
+    }
+
+    fn push(&mut self, nodes: &mut [ListMeta], list: u8, idx: usize) {
+        let l = &mut self.0[list as usize];
+        nodes[idx].list = list;
+        nodes[idx].prev = l.back;
+        nodes[idx].next = NULL;
+        if l.back == NULL {
+            l.front = idx;
+        } else {
+            nodes[l.back].next = idx;
+        }
+        l.back = idx;
+        l.len += 1;
+    }
+
+    fn unlink(&mut self, nodes: &mut [ListMeta], idx: usize) {
+        let l = &mut self.0[nodes[idx].list as usize];
+        let (prev, next) = (nodes[idx].prev, nodes[idx].next);
+        if prev == NULL {
+            l.front = next;
+        } else {
+            nodes[prev].next = next;
+        }
+        if next == NULL {
+            l.back = prev;
+        } else {
+            nodes[next].prev = prev;
+        }
+        l.len -= 1;
+    }
+
+    fn pop(&mut self, nodes: &mut [ListMeta], list: u8) -> usize {
+        let idx = self.0[list as usize].front;
+        self.unlink(nodes, idx);
+        idx
+    }
+
+    // Returns a frame to the free list, without remembering its page.
+    fn release(&mut self, nodes: &mut [ListMeta], idx: usize) {
+        if nodes[idx].list != FREE {
+            self.unlink(nodes, idx);
+            nodes[idx].page = u64::MAX;
+            nodes[idx].freq = 0;
+            self.push(nodes, FREE, idx);
+        }
+    }
+
+    // Puts `page` on `list` in a frame emptied by `release`, forgetting any ghost of it. Frames
+    // handed out by `replace` are on a list already and left alone.
+    fn claim<G: Ghosts>(
+        &mut self,
+        nodes: &mut [ListMeta],
+        ghosts: &mut G,
+        idx: usize,
+        page: u64,
+        list: u8,
+    ) {
+        if nodes[idx].list == FREE {
+            let ghost = ghosts.find(nodes, page);
+            if ghost != NULL {
+                self.forget(nodes, ghosts, ghost);
+            }
+            self.unlink(nodes, idx);
+            nodes[idx].page = page;
+            nodes[idx].freq = 0;
+            self.push(nodes, list, idx);
+        }
+    }
+
+    fn forget<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, idx: usize) {
+        self.unlink(nodes, idx);
+        ghosts.remove(nodes[idx].page);
+        nodes[idx].page = u64::MAX;
+        self.push(nodes, GHOST_FREE, idx);
+    }
+
+    fn drop_ghost<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, list: u8) {
+        let idx = self.0[list as usize].front;
+        self.forget(nodes, ghosts, idx);
+    }
+
+    // Needs a free ghost entry.
+    fn remember<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, list: u8, page: u64) {
+        let slot = self.pop(nodes, GHOST_FREE);
+        nodes[slot].page = page;
+        self.push(nodes, list, slot);
+        ghosts.insert(page, slot);
+    }
+}
+
+const ARC_T1: u8 = 1;
+const ARC_T2: u8 = 2;
+const ARC_B1: u8 = 3;
+const ARC_B2: u8 = 4;
+
+// ARC keeps as many ghost entries as frames. Nodes `0..count` are the frames, resident in T1 or
+// T2, and nodes `count..(count * 2)` hold the pages recently evicted from them, in B1 or B2.
+struct ARCState {
+    lists: Lists,
+    count: usize,
+    target: usize,
+}
+
+impl ARCState {
+    fn new(nodes: &mut [ListMeta]) -> Self {
+        let count = nodes.len() / 2;
+        Self {
+            lists: Lists::new(nodes, count),
+            count,
+            target: 0,
+        }
+    }
+
+    fn len(&self, list: u8) -> usize {
+        self.lists.len(list)
+    }
+
+    // REPLACE from the ARC paper: evicts the LRU page of T1 or T2, remembering it in B1 or B2.
+    fn evict<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, in_b2: bool) -> usize {
+        let t1 = self.len(ARC_T1);
+        let from_t1 =
+            self.len(ARC_T2) == 0 || (t1 > 0 && (t1 > self.target || (in_b2 && t1 == self.target)));
+        let (frame, list) = if from_t1 {
+            (self.lists.pop(nodes, ARC_T1), ARC_B1)
+        } else {
+            (self.lists.pop(nodes, ARC_T2), ARC_B2)
+        };
+        if self.len(GHOST_FREE) == 0 {
+            let full = if self.len(ARC_B1) > 0 { ARC_B1 } else { ARC_B2 };
+            self.lists.drop_ghost(nodes, ghosts, full);
+        }
+        let page = nodes[frame].page;
+        self.lists.remember(nodes, ghosts, list, page);
+        frame
+    }
+
+    fn replace<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, page: u64) -> usize {
+        let ghost = ghosts.find(nodes, page);
+        let list = if ghost == NULL {
+            NO_LIST
+        } else {
+            nodes[ghost].list
+        };
+        let (in_b1, in_b2) = (list == ARC_B1, list == ARC_B2);
+        let (b1, b2) = (self.len(ARC_B1), self.len(ARC_B2));
+        if in_b1 {
+            self.target = std::cmp::min(self.count, self.target + std::cmp::max(b2 / b1, 1));
+        } else if in_b2 {
+            self.target = self.target.saturating_sub(std::cmp::max(b1 / b2, 1));
+        }
+        if ghost != NULL {
+            self.lists.forget(nodes, ghosts, ghost);
+        }
+
+        let frame = if self.len(FREE) > 0 {
+            self.lists.pop(nodes, FREE)
+        } else if in_b1 || in_b2 {
+            self.evict(nodes, ghosts, in_b2)
+        } else if self.len(ARC_T1) + self.len(ARC_B1) >= self.count {
+            if self.len(ARC_T1) < self.count {
+                self.lists.drop_ghost(nodes, ghosts, ARC_B1);
+                self.evict(nodes, ghosts, false)
+            } else {
+                self.lists.pop(nodes, ARC_T1)
+            }
+        } else {
+            if self.len(ARC_T1) + self.len(ARC_T2) + self.len(ARC_B1) + self.len(ARC_B2)
+                >= self.count * 2
+            {
+                self.lists.drop_ghost(nodes, ghosts, ARC_B2);
+            }
+            self.evict(nodes, ghosts, false)
+        };
+
+        nodes[frame].page = page;
+        let list = if in_b1 || in_b2 { ARC_T2 } else { ARC_T1 };
+        self.lists.push(nodes, list, frame);
+        frame
+    }
+
+    fn record_access(&mut self, nodes: &mut [ListMeta], frame: usize) {
+        let list = nodes[frame].list;
+        if list == ARC_T1 || list == ARC_T2 {
+            self.lists.unlink(nodes, frame);
+            self.lists.push(nodes, ARC_T2, frame);
+        }
+    }
+}
+
+// `Nodes::LEN` must be twice the number of ways.
+pub struct ARC<Nodes: Array<ARCMeta>> {
+    nodes: Nodes,
+    state: ARCState,
+}
+
+impl<Nodes: Array<ARCMeta>> Replace for ARC<Nodes> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Nodes::LEN / 2);
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<ARCMeta>() * 2;
+
+    fn new(count: usize) -> Self {
+        assert!(
+            Nodes::LEN == 2 * count,
+            "io_cache ARC needs two nodes per way"
+        );
+        let mut nodes = Nodes::new();
+        let state = ARCState::new(nodes.get_mut());
+        Self { nodes, state }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        let count = self.state.count;
+        self.state
+            .replace(self.nodes.get_mut(), &mut GhostScan(count), page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.state.record_access(self.nodes.get_mut(), idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let count = self.state.count;
+        let nodes = self.nodes.get_mut();
+        let ghosts = &mut GhostScan(count);
+        self.state.lists.claim(nodes, ghosts, idx, page, ARC_T1);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(self.nodes.get_mut(), idx);
+    }
+}
+
+const SLRU_PROBATION: u8 = 1;
+const SLRU_PROTECTED: u8 = 2;
+
+// New pages enter the probationary segment and move to the protected one when accessed again.
+// The protected segment holds at most `ratio` of the frames; its LRU page is demoted back to
+// probation when it overflows. Victims come from probation first.
+struct SLRUState {
+    lists: Lists,
+    protected: usize,
+}
+
+impl SLRUState {
+    fn new(nodes: &mut [ListMeta], ratio: f32) -> Self {
+        let count = nodes.len();
+        Self {
+            lists: Lists::new(nodes, count),
+            protected: std::cmp::min((count as f32 * ratio) as usize, count),
+        }
+    }
+
+    fn replace(&mut self, nodes: &mut [ListMeta], page: u64) -> usize {
+        let list = if self.lists.len(FREE) > 0 {
+            FREE
+        } else if self.lists.len(SLRU_PROBATION) > 0 {
+            SLRU_PROBATION
+        } else {
+            SLRU_PROTECTED
+        };
+        let frame = self.lists.pop(nodes, list);
+        nodes[frame].page = page;
+        self.lists.push(nodes, SLRU_PROBATION, frame);
+        frame
+    }
+
+    fn record_access(&mut self, nodes: &mut [ListMeta], frame: usize) {
+        match nodes[frame].list {
+            SLRU_PROBATION | SLRU_PROTECTED => {
+                self.lists.unlink(nodes, frame);
+                self.lists.push(nodes, SLRU_PROTECTED, frame);
+                if self.lists.len(SLRU_PROTECTED) > self.protected {
+                    let demoted = self.lists.pop(nodes, SLRU_PROTECTED);
+                    self.lists.push(nodes, SLRU_PROBATION, demoted);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// `Ratio` is the share of the ways making up the protected segment.
+pub struct SLRU<Meta: Array<SLRUMeta>, Ratio: ConstF32> {
+    nodes: Meta,
+    state: SLRUState,
+    _phantom: std::marker::PhantomData<Ratio>,
+}
+
+impl<Meta: Array<SLRUMeta>, Ratio: ConstF32> Replace for SLRU<Meta, Ratio> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Meta::LEN);
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<SLRUMeta>();
+
+    fn new(_: usize) -> Self {
+        let mut nodes = Meta::new();
+        let state = SLRUState::new(nodes.get_mut(), Ratio::VALUE);
+        Self {
+            nodes,
+            state,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state.replace(self.nodes.get_mut(), page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.state.record_access(self.nodes.get_mut(), idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let nodes = self.nodes.get_mut();
+        let ghosts = &mut GhostScan(nodes.len());
+        self.state
+            .lists
+            .claim(nodes, ghosts, idx, page, SLRU_PROBATION);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(self.nodes.get_mut(), idx);
+    }
+}
+
+const TWOQ_A1IN: u8 = 1;
+const TWOQ_AM: u8 = 2;
+const TWOQ_A1OUT: u8 = 3;
+
+// Full 2Q: new pages enter the A1in FIFO, which holds up to `ratio` of the frames. Pages evicted
+// from it are remembered in the A1out ghost FIFO, and only pages missed again while there are
+// promoted to the Am LRU. Hits in A1in don't count, so correlated references don't promote.
+struct TwoQState {
+    lists: Lists,
+    count: usize,
+    kin: usize,
+}
+
+impl TwoQState {
+    fn new(nodes: &mut [ListMeta], count: usize, ratio: f32) -> Self {
+        Self {
+            lists: Lists::new(nodes, count),
+            count,
+            kin: (count as f32 * ratio) as usize,
+        }
+    }
+
+    fn replace<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, page: u64) -> usize {
+        let ghost = ghosts.find(nodes, page);
+        if ghost != NULL {
+            self.lists.forget(nodes, ghosts, ghost);
+        }
+
+        let frame = if self.lists.len(FREE) > 0 {
+            self.lists.pop(nodes, FREE)
+        } else if self.lists.len(TWOQ_A1IN) > self.kin || self.lists.len(TWOQ_AM) == 0 {
+            let frame = self.lists.pop(nodes, TWOQ_A1IN);
+            if nodes.len() > self.count {
+                if self.lists.len(GHOST_FREE) == 0 {
+                    self.lists.drop_ghost(nodes, ghosts, TWOQ_A1OUT);
+                }
+                let evicted = nodes[frame].page;
+                self.lists.remember(nodes, ghosts, TWOQ_A1OUT, evicted);
+            }
+            frame
+        } else {
+            self.lists.pop(nodes, TWOQ_AM)
+        };
+
+        nodes[frame].page = page;
+        let list = if ghost != NULL { TWOQ_AM } else { TWOQ_A1IN };
+        self.lists.push(nodes, list, frame);
+        frame
+    }
+
+    fn record_access(&mut self, nodes: &mut [ListMeta], frame: usize) {
+        if nodes[frame].list == TWOQ_AM {
+            self.lists.unlink(nodes, frame);
+            self.lists.push(nodes, TWOQ_AM, frame);
+        }
+    }
+}
+
+// `Size` is the number of ways and the remaining `Nodes::LEN - Size::VALUE` nodes make up A1out;
+// half the ways is the usual choice. `Ratio` is the share of the ways making up A1in.
+pub struct TwoQ<Size: ConstUsize, Nodes: Array<TwoQMeta>, Ratio: ConstF32> {
+    nodes: Nodes,
+    state: TwoQState,
+    _phantom: std::marker::PhantomData<(Size, Ratio)>,
+}
+
+impl<Size: ConstUsize, Nodes: Array<TwoQMeta>, Ratio: ConstF32> Replace
+    for TwoQ<Size, Nodes, Ratio>
+{
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Size::VALUE);
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<TwoQMeta>() * Nodes::LEN / Size::VALUE;
+
+    fn new(_: usize) -> Self {
+        assert!(
+            Nodes::LEN >= Size::VALUE,
+            "io_cache 2Q needs a node for every way"
+        );
+        let mut nodes = Nodes::new();
+        let state = TwoQState::new(nodes.get_mut(), Size::VALUE, Ratio::VALUE);
+        Self {
+            nodes,
+            state,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state
+            .replace(self.nodes.get_mut(), &mut GhostScan(Size::VALUE), page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.state.record_access(self.nodes.get_mut(), idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let nodes = self.nodes.get_mut();
+        let ghosts = &mut GhostScan(Size::VALUE);
+        self.state.lists.claim(nodes, ghosts, idx, page, TWOQ_A1IN);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(self.nodes.get_mut(), idx);
+    }
+}
+
+const S3_SMALL: u8 = 1;
+const S3_MAIN: u8 = 2;
+const S3_GHOST: u8 = 3;
+const S3_MAX_FREQ: u8 = 3;
+
+// S3-FIFO (Yang et al.): new pages enter a small FIFO holding a tenth of the frames and move to
+// the main FIFO if they were accessed before reaching its end; the others are only remembered in
+// the ghost FIFO, and missing them again inserts them straight into the main FIFO. Pages at the
+// end of the main FIFO are reinserted while their access count lasts. A hit only bumps a counter,
+// so no list is touched on the read path.
+struct S3FIFOState {
+    lists: Lists,
+    small: usize,
+}
+
+impl S3FIFOState {
+    fn new(nodes: &mut [ListMeta], count: usize) -> Self {
+        Self {
+            lists: Lists::new(nodes, count),
+            small: std::cmp::max(count / 10, 1),
+        }
+    }
+
+    fn evict<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G) -> usize {
+        loop {
+            if self.lists.len(S3_SMALL) >= self.small || self.lists.len(S3_MAIN) == 0 {
+                let frame = self.lists.pop(nodes, S3_SMALL);
+                if nodes[frame].freq > 0 {
+                    nodes[frame].freq = 0;
+                    self.lists.push(nodes, S3_MAIN, frame);
+                    continue;
+                }
+                if self.lists.len(GHOST_FREE) > 0 || self.lists.len(S3_GHOST) > 0 {
+                    if self.lists.len(GHOST_FREE) == 0 {
+                        self.lists.drop_ghost(nodes, ghosts, S3_GHOST);
+                    }
+                    let page = nodes[frame].page;
+                    self.lists.remember(nodes, ghosts, S3_GHOST, page);
+                }
+                return frame;
+            }
+            let frame = self.lists.pop(nodes, S3_MAIN);
+            if nodes[frame].freq > 0 {
+                nodes[frame].freq -= 1;
+                self.lists.push(nodes, S3_MAIN, frame);
+                continue;
+            }
+            return frame;
+        }
+    }
+
+    fn replace<G: Ghosts>(&mut self, nodes: &mut [ListMeta], ghosts: &mut G, page: u64) -> usize {
+        // Look the ghost up before evicting, which may drop the oldest ghost.
+        let ghost = ghosts.find(nodes, page);
+        let list = if ghost != NULL {
+            self.lists.forget(nodes, ghosts, ghost);
+            S3_MAIN
+        } else {
+            S3_SMALL
+        };
+        let frame = if self.lists.len(FREE) > 0 {
+            self.lists.pop(nodes, FREE)
+        } else {
+            self.evict(nodes, ghosts)
+        };
+        nodes[frame].page = page;
+        nodes[frame].freq = 0;
+        self.lists.push(nodes, list, frame);
+        frame
+    }
+
+    fn record_access(nodes: &mut [ListMeta], frame: usize) {
+        nodes[frame].freq = std::cmp::min(nodes[frame].freq + 1, S3_MAX_FREQ);
+    }
+}
+
+// `Size` is the number of ways and the remaining `Nodes::LEN - Size::VALUE` nodes make up the
+// ghost FIFO; as many as the main FIFO holds, nine tenths of the ways, is the usual choice.
+pub struct S3FIFO<Size: ConstUsize, Nodes: Array<S3FIFOMeta>> {
+    nodes: Nodes,
+    state: S3FIFOState,
+    _phantom: std::marker::PhantomData<Size>,
+}
+
+impl<Size: ConstUsize, Nodes: Array<S3FIFOMeta>> Replace for S3FIFO<Size, Nodes> {
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK * Size::VALUE);
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<S3FIFOMeta>() * Nodes::LEN / Size::VALUE;
+
+    fn new(_: usize) -> Self {
+        assert!(
+            Nodes::LEN >= Size::VALUE,
+            "io_cache S3-FIFO needs a node for every way"
+        );
+        let mut nodes = Nodes::new();
+        let state = S3FIFOState::new(nodes.get_mut(), Size::VALUE);
+        Self {
+            nodes,
+            state,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state
+            .replace(self.nodes.get_mut(), &mut GhostScan(Size::VALUE), page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        S3FIFOState::record_access(self.nodes.get_mut(), idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let nodes = self.nodes.get_mut();
+        let ghosts = &mut GhostScan(Size::VALUE);
+        self.state.lists.claim(nodes, ghosts, idx, page, S3_SMALL);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(self.nodes.get_mut(), idx);
+    }
+}
+
+pub struct FARandom<Rng: RandomSource> {
+    rng: Rng,
+    count: usize,
+    free: FreeFrames,
+}
+
+impl<Rng: RandomSource> Replace for FARandom<Rng> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FreeFrames::MEM_PER_BLOCK;
+
+    fn new(count: usize) -> Self {
+        Self {
+            rng: Rng::new(0),
+            count,
+            free: FreeFrames::full(count),
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        match self.free.pop() {
+            Some(frame) => frame,
+            None => random_below(&mut self.rng, self.count),
+        }
+    }
+
+    fn record_access(&mut self, _: usize) {}
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.free.take(idx);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.free.put(idx);
+    }
+}
+
+pub struct FALRU {
+    list: Vec<LRUMeta>,
+    front: usize,
+    back: usize,
+}
+
+impl Replace for FALRU {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<LRUMeta>();
+
+    fn new(count: usize) -> Self {
+        let cm1 = count - 1;
+        let mut meta = vec![LRUMeta::default(); count];
+        for (idx, block) in meta.iter_mut().enumerate() {
+            if idx == 0 {
+                block.prev = NULL;
+            } else {
+                block.prev = idx - 1;
+            }
+            if idx == cm1 {
+                block.next = NULL;
+            } else {
+                block.next = idx + 1;
+            }
+        }
+        Self {
+            list: meta,
+            front: 0,
+            back: cm1,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        let ret = self.front;
+        let next = self.list[ret].next;
+        if next != NULL {
+            self.front = next;
+            let back = self.back;
+            self.back = ret;
+            {
+                self.list[back].next = ret;
+                let r = &mut self.list[ret];
+                r.prev = back;
+                r.next = NULL;
+            }
+        }
+        ret
+    }
+
+    fn record_access(&mut self, block_idx: usize) {
+        let back = self.back;
+        if back != block_idx {
+            let front = self.front;
+            if front == block_idx {
+                let next = self.list[front].next;
+                self.front = next;
+                self.back = front;
+                {
+                    self.list[next].prev = NULL;
+                    self.list[back].next = front;
+                    let b = &mut self.list[front];
+                    b.prev = back;
+                    b.next = NULL;
+                }
+            } else {
+                self.back = block_idx;
+                let (next, prev) = {
+                    let data = &self.list[block_idx];
+                    (data.next, data.prev)
+                };
+                {
+                    self.list[next].prev = prev;
+                    self.list[prev].next = next;
+                    self.list[block_idx].prev = back;
+                    self.list[block_idx].next = NULL;
+                    self.list[back].next = block_idx;
+                }
+            }
+        }
+    }
+
+    fn record_insert(&mut self, block_idx: usize, _: u64) {
+        self.record_access(block_idx);
+    }
+
+    fn record_remove(&mut self, block_idx: usize) {
+        let front = self.front;
+        if front != block_idx {
+            let data = &mut self.list;
+            let (next, prev) = (data[block_idx].next, data[block_idx].prev);
+            data[prev].next = next;
+            if next == NULL {
+                self.back = prev;
+            } else {
+                data[next].prev = prev;
+            }
+            data[block_idx].prev = NULL;
+            data[block_idx].next = front;
+            data[front].prev = block_idx;
+            self.front = block_idx;
+        }
+    }
+}
+
+pub struct FACFLRU<Window: ConstF32> {
+    lru: FALRU,
+    dirty: Vec<bool>,
+    window: usize,
+    _phantom: std::marker::PhantomData<Window>,
+}
+
+impl<Window: ConstF32> Replace for FACFLRU<Window> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FALRU::META_MEM_PER_BLOCK + std::mem::size_of::<bool>();
+
+    fn new(count: usize) -> Self {
+        Self {
+            lru: FALRU::new(count),
+            dirty: vec![false; count],
+            window: cflru_window::<Window>(count),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        let ret = cflru_victim(&self.lru.list, &self.dirty, self.lru.front, self.window);
+        if ret == self.lru.front {
+            self.lru.replace(page)
+        } else {
+            self.lru.record_access(ret);
+            ret
+        }
+    }
+
+    fn record_access(&mut self, block_idx: usize) {
+        self.lru.record_access(block_idx);
+    }
+
+    fn record_insert(&mut self, block_idx: usize, page: u64) {
+        self.dirty[block_idx] = false;
+        self.lru.record_insert(block_idx, page);
+    }
+
+    fn record_remove(&mut self, block_idx: usize) {
+        self.dirty[block_idx] = false;
+        self.lru.record_remove(block_idx);
+    }
+
+    fn record_dirty(&mut self, block_idx: usize, dirty: bool) {
+        self.dirty[block_idx] = dirty;
+    }
+}
+
+struct FAHeap<T: Default + Clone> {
+    data: Vec<HeapElem<T>>,
+    queue: Vec<usize>,
+}
+
+impl<T: Default + Clone> FAHeap<T> {
+    const META_MEM_PER_BLOCK: usize =
+        std::mem::size_of::<HeapElem<T>>() + std::mem::size_of::<usize>();
+
+    // The keys set by `f` have to be in heap order by frame already, e.g. all equal.
+    fn new<F: FnMut(usize, &mut T)>(count: usize, mut f: F) -> Self {
+        let mut data: Vec<HeapElem<T>> = vec![HeapElem::default(); count];
+        for (idx, elem) in data.iter_mut().enumerate() {
+            f(idx, &mut elem.data);
+        }
+        let mut queue = vec![0; count];
+        heap_init(&mut data, &mut queue);
+        Self { data, queue }
+    }
+
+    fn top(&self) -> usize {
+        self.queue[0]
+    }
+
+    fn get(&self, idx: usize) -> &T {
+        &self.data[idx].data
+    }
+
+    fn update<F: FnOnce(&mut T), Cmp: Fn(&T, &T) -> i8>(&mut self, idx: usize, f: F, cmp: Cmp) {
+        f(&mut self.data[idx].data);
+        heap_fix(&mut self.data, &mut self.queue, idx, cmp);
+    }
+
+    fn update_all<F: FnMut(&mut T), Cmp: Fn(&T, &T) -> i8>(&mut self, f: F, cmp: Cmp) {
+        heap_update_all(&mut self.data, &mut self.queue, f, cmp);
+    }
+}
+
+pub struct FALFU<Aging: LFUAging> {
+    heap: FAHeap<LFUMeta>,
+    aging: Aging,
+    now: u64,
+}
+
+impl<Aging: LFUAging> Replace for FALFU<Aging> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FAHeap::<LFUMeta>::META_MEM_PER_BLOCK;
+
+    fn new(count: usize) -> Self {
+        Self {
+            heap: FAHeap::new(count, |_, _| {}),
+            aging: Aging::new(),
+            now: 0,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        if self.aging.tick() {
+            self.heap.update_all(lfu_halve, lfu_cmp);
+        }
+        self.now += 1;
+        let now = self.now;
+        let ret = self.heap.top();
+        self.heap.update(
+            ret,
+            |m| {
+                m.count = 1;
+                m.time = now;
+            },
+            lfu_cmp,
+        );
+        ret
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        if self.aging.tick() {
+            self.heap.update_all(lfu_halve, lfu_cmp);
+        }
+        self.now += 1;
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |m| {
+                m.count = m.count.saturating_add(1);
+                m.time = now;
+            },
+            lfu_cmp,
+        );
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |m| {
+                m.count = 1;
+                m.time = now;
+            },
+            lfu_cmp,
+        );
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.heap.update(idx, |m| *m = LFUMeta::default(), lfu_cmp);
+    }
+}
+
+pub struct FALRFU<Rate: ConstF32> {
+    heap: FAHeap<LRFUMeta>,
+    now: u64,
+    rate: f32,
+    _marker: std::marker::PhantomData<Rate>,
+}
+
+impl<Rate: ConstF32> Replace for FALRFU<Rate> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FAHeap::<LRFUMeta>::META_MEM_PER_BLOCK;
+
+    fn new(count: usize) -> Self {
+        lrfu_check_rate(Rate::VALUE);
+        Self {
+            heap: FAHeap::new(count, |_, _| {}),
+            now: 0,
+            rate: Rate::VALUE,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        let ret = self.heap.top();
+        let now = self.now;
+        self.heap.update(
+            ret,
+            |b| {
+                b.crf = 1.0;
+                b.time = now;
+            },
+            lrfu_cmp(self.rate),
+        );
+        ret
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.now += 1;
+        let (now, rate) = (self.now, self.rate);
+        self.heap.update(
+            idx,
+            |b| {
+                b.crf = crf_calc(b, now, rate) + 1.0;
+                b.time = now;
+            },
+            lrfu_cmp(rate),
+        );
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |b| {
+                b.crf = 1.0;
+                b.time = now;
+            },
+            lrfu_cmp(self.rate),
+        );
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        let now = self.now;
+        self.heap.update(
+            idx,
+            |b| {
+                b.crf = 0.0;
+                b.time = now;
+            },
+            lrfu_cmp(self.rate),
+        );
+    }
+}
+
+impl<Rate: ConstF32> LRFURate for FALRFU<Rate> {
+    fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    fn set_rate(&mut self, rate: f32) {
+        lrfu_check_rate(rate);
+        self.rate = rate;
+        self.heap.update_all(|_| {}, lrfu_cmp(rate));
+    }
+}
+
+pub struct FAGreedyDual {
+    heap: FAHeap<GDMeta>,
+    inflation: u64,
+}
+
+impl Replace for FAGreedyDual {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FAHeap::<GDMeta>::META_MEM_PER_BLOCK;
+    const COST_AWARE: bool = true;
+
+    fn new(count: usize) -> Self {
+        Self {
+            heap: FAHeap::new(count, |_, _| {}),
+            inflation: 0,
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        let ret = self.heap.top();
+        self.inflation = std::cmp::max(self.inflation, self.heap.get(ret).worth);
+        self.heap
+            .update(ret, gd_insert(self.inflation, GD_MIN_COST), gd_cmp);
+        ret
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.heap.update(idx, gd_touch(self.inflation), gd_cmp);
+    }
+
+    fn record_cost(&mut self, idx: usize, cost: u64) {
+        self.heap
+            .update(idx, gd_insert(self.inflation, cost), gd_cmp);
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.heap
+            .update(idx, gd_insert(self.inflation, GD_MIN_COST), gd_cmp);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.heap.update(idx, |m| *m = GDMeta::default(), gd_cmp);
+    }
+}
+
+pub struct FAFIFO {
+    curr: usize,
+    count: usize,
+    free: FreeFrames,
+}
+
+impl Replace for FAFIFO {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FreeFrames::MEM_PER_BLOCK;
+
+    fn new(count: usize) -> Self {
+        Self {
+            curr: 0,
+            count,
+            free: FreeFrames::empty(count),
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        if let Some(frame) = self.free.pop() {
+            return frame;
+        }
+        let ret = self.curr;
+        self.curr = (self.curr + 1) % self.count;
+        ret
+    }
+
+    fn record_access(&mut self, _: usize) {}
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.free.take(idx);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.free.put(idx);
+    }
+}
+
+pub struct FAClock {
+    bits: Vec<u64>,
+    hand: usize,
+    count: usize,
+    free: FreeFrames,
+}
+
+impl Replace for FAClock {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = FreeFrames::MEM_PER_BLOCK;
+
+    fn new(count: usize) -> Self {
+        Self {
+            bits: vec![0; count.div_ceil(64)],
+            hand: 0,
+            count,
+            free: FreeFrames::empty(count),
+        }
+    }
+
+    fn replace(&mut self, _: u64) -> usize {
+        if let Some(frame) = self.free.pop() {
+            return frame;
+        }
+        loop {
+            let idx = self.hand;
+            self.hand = (self.hand + 1) % self.count;
+            let mask = 1 << (idx % 64);
+            if self.bits[idx / 64] & mask == 0 {
+                return idx;
+            }
+            self.bits[idx / 64] &= !mask;
+        }
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.bits[idx / 64] |= 1 << (idx % 64);
+    }
+
+    fn record_insert(&mut self, idx: usize, _: u64) {
+        self.free.take(idx);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.bits[idx / 64] &= !(1 << (idx % 64));
+        self.free.put(idx);
+    }
+}
+
+pub struct FAARC {
+    nodes: Vec<ARCMeta>,
+    ghosts: std::collections::HashMap<u64, usize>,
+    state: ARCState,
+}
+
+impl Replace for FAARC {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize =
+        (std::mem::size_of::<ARCMeta>() + std::mem::size_of::<(u64, usize)>()) * 2;
+
+    fn new(count: usize) -> Self {
+        let mut nodes = vec![ARCMeta::default(); count * 2];
+        let state = ARCState::new(&mut nodes);
+        Self {
+            nodes,
+            ghosts: std::collections::HashMap::with_capacity(count),
+            state,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state.replace(&mut self.nodes, &mut self.ghosts, page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.state.record_access(&mut self.nodes, idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let (nodes, ghosts) = (&mut self.nodes, &mut self.ghosts);
+        self.state.lists.claim(nodes, ghosts, idx, page, ARC_T1);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(&mut self.nodes, idx);
+    }
+}
+
+pub struct FASLRU<Ratio: ConstF32> {
+    nodes: Vec<SLRUMeta>,
+    state: SLRUState,
+    _phantom: std::marker::PhantomData<Ratio>,
+}
+
+impl<Ratio: ConstF32> Replace for FASLRU<Ratio> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<SLRUMeta>();
+
+    fn new(count: usize) -> Self {
+        let mut nodes = vec![SLRUMeta::default(); count];
+        let state = SLRUState::new(&mut nodes, Ratio::VALUE);
+        Self {
+            nodes,
+            state,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state.replace(&mut self.nodes, page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.state.record_access(&mut self.nodes, idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let ghosts = &mut GhostScan(self.nodes.len());
+        self.state
+            .lists
+            .claim(&mut self.nodes, ghosts, idx, page, SLRU_PROBATION);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(&mut self.nodes, idx);
+    }
+}
+
+// A1out remembers half as many pages as there are frames.
+pub struct FATwoQ<Ratio: ConstF32> {
+    nodes: Vec<TwoQMeta>,
+    ghosts: std::collections::HashMap<u64, usize>,
+    state: TwoQState,
+    _phantom: std::marker::PhantomData<Ratio>,
+}
+
+impl<Ratio: ConstF32> Replace for FATwoQ<Ratio> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<TwoQMeta>()
+        + (std::mem::size_of::<TwoQMeta>() + std::mem::size_of::<(u64, usize)>()) / 2;
+
+    fn new(count: usize) -> Self {
+        let mut nodes = vec![TwoQMeta::default(); count + count / 2];
+        let state = TwoQState::new(&mut nodes, count, Ratio::VALUE);
+        Self {
+            nodes,
+            ghosts: std::collections::HashMap::with_capacity(count / 2),
+            state,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state.replace(&mut self.nodes, &mut self.ghosts, page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.state.record_access(&mut self.nodes, idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let (nodes, ghosts) = (&mut self.nodes, &mut self.ghosts);
+        self.state.lists.claim(nodes, ghosts, idx, page, TWOQ_A1IN);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(&mut self.nodes, idx);
+    }
+}
+
+// The ghost FIFO remembers as many pages as the main FIFO holds.
+pub struct FAS3FIFO {
+    nodes: Vec<S3FIFOMeta>,
+    ghosts: std::collections::HashMap<u64, usize>,
+    state: S3FIFOState,
+}
+
+impl Replace for FAS3FIFO {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize =
+        std::mem::size_of::<S3FIFOMeta>() * 2 + std::mem::size_of::<(u64, usize)>();
+
+    fn new(count: usize) -> Self {
+        let ghosts = count - std::cmp::min(std::cmp::max(count / 10, 1), count);
+        let mut nodes = vec![S3FIFOMeta::default(); count + ghosts];
+        let state = S3FIFOState::new(&mut nodes, count);
+        Self {
+            nodes,
+            ghosts: std::collections::HashMap::with_capacity(ghosts),
+            state,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        self.state.replace(&mut self.nodes, &mut self.ghosts, page)
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        S3FIFOState::record_access(&mut self.nodes, idx);
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        let (nodes, ghosts) = (&mut self.nodes, &mut self.ghosts);
+        self.state.lists.claim(nodes, ghosts, idx, page, S3_SMALL);
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        self.state.lists.release(&mut self.nodes, idx);
+    }
+}
+
+const LIRS_FREE: u8 = 0;
+const LIRS_LIR: u8 = 1;
+const LIRS_HIR: u8 = 2;
+const LIRS_GHOST: u8 = 3;
+
+#[derive(Clone, Copy)]
+struct LIRSLink {
+    prev: usize,
+    next: usize,
+}
+
+const LIRS_UNLINKED: LIRSLink = LIRSLink {
+    prev: NULL,
+    next: NULL,
+};
+
+// Nodes `0..count` are the frames, nodes `count..(count * 2)` the non-resident HIR pages still in
+// the stack. A resident HIR page can be on the stack and the queue at once, so every node has a
+// link for each; ghosts reuse their queue link for the FIFO bounding them.
+#[derive(Clone, Copy)]
+struct LIRSNode {
+    page: u64,
+    stack: LIRSLink,
+    queue: LIRSLink,
+    in_stack: bool,
+    state: u8,
+}
+
+fn lirs_stack(node: &mut LIRSNode) -> &mut LIRSLink {
+    &mut node.stack
+}
+
+fn lirs_queue(node: &mut LIRSNode) -> &mut LIRSLink {
+    &mut node.queue
+}
+
+#[derive(Clone, Copy)]
+struct LIRSChain {
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+type LIRSLinkOf = fn(&mut LIRSNode) -> &mut LIRSLink;
+
+impl LIRSChain {
+    fn new() -> Self {
+        Self {
+            front: NULL,
+            back: NULL,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, nodes: &mut [LIRSNode], link: LIRSLinkOf, idx: usize) {
+        *link(&mut nodes[idx]) = LIRSLink {
+            prev: self.back,
+            next: NULL,
+        };
+        if self.back == NULL {
+            self.front = idx;
+        } else {
+            link(&mut nodes[self.back]).next = idx;
+        }
+        self.back = idx;
+        self.len += 1;
+    }
+
+    fn unlink(&mut self, nodes: &mut [LIRSNode], link: LIRSLinkOf, idx: usize) {
+        let LIRSLink { prev, next } = *link(&mut nodes[idx]);
+        if prev == NULL {
+            self.front = next;
+        } else {
+            link(&mut nodes[prev]).next = next;
+        }
+        if next == NULL {
+            self.back = prev;
+        } else {
+            link(&mut nodes[next]).prev = prev;
+        }
+        *link(&mut nodes[idx]) = LIRS_UNLINKED;
+        self.len -= 1;
+    }
+
+    // Puts `new` in the place of `old`.
+    fn swap(&mut self, nodes: &mut [LIRSNode], link: LIRSLinkOf, old: usize, new: usize) {
+        let LIRSLink { prev, next } = *link(&mut nodes[old]);
+        *link(&mut nodes[new]) = LIRSLink { prev, next };
+        *link(&mut nodes[old]) = LIRS_UNLINKED;
+        if prev == NULL {
+            self.front = new;
+        } else {
+            link(&mut nodes[prev]).next = new;
+        }
+        if next == NULL {
+            self.back = new;
+        } else {
+            link(&mut nodes[next]).prev = new;
+        }
+    }
+}
+
+// LIRS (Jiang & Zhang). The stack orders LIR and HIR pages by recency and always has a LIR page at
+// the bottom; a HIR page reused while still on the stack has a smaller reuse distance than that
+// page and takes its LIR status. Victims come from the queue of resident HIR pages, so a loop
+// slightly larger than the cache keeps most of it resident instead of missing on every access.
+pub struct FALIRS<Ratio: ConstF32> {
+    nodes: Vec<LIRSNode>,
+    ghosts: std::collections::HashMap<u64, usize>,
+    stack: LIRSChain,
+    queue: LIRSChain,
+    ghost_queue: LIRSChain,
+    free: Vec<usize>,
+    free_ghosts: Vec<usize>,
+    lir: usize,
+    lir_cap: usize,
+    _phantom: std::marker::PhantomData<Ratio>,
+}
+
+impl<Ratio: ConstF32> FALIRS<Ratio> {
+    fn push_stack(&mut self, idx: usize) {
+        self.stack.push(&mut self.nodes, lirs_stack, idx);
+        self.nodes[idx].in_stack = true;
+    }
+
+    fn unlink_stack(&mut self, idx: usize) {
+        self.stack.unlink(&mut self.nodes, lirs_stack, idx);
+        self.nodes[idx].in_stack = false;
+    }
+
+    fn drop_ghost(&mut self, idx: usize) {
+        self.unlink_stack(idx);
+        self.ghost_queue.unlink(&mut self.nodes, lirs_queue, idx);
+        self.ghosts.remove(&self.nodes[idx].page);
+        self.nodes[idx].page = u64::MAX;
+        self.nodes[idx].state = LIRS_FREE;
+        self.free_ghosts.push(idx);
+    }
+
+    // Stack pruning: pops HIR pages off the bottom until a LIR page is there.
+    fn prune(&mut self) {
+        while self.stack.front != NULL && self.nodes[self.stack.front].state != LIRS_LIR {
+            let idx = self.stack.front;
+            if self.nodes[idx].state == LIRS_GHOST {
+                self.drop_ghost(idx);
+            } else {
+                self.unlink_stack(idx);
+            }
+        }
+    }
+
+    // Turns the bottom LIR page into a resident HIR page once there are too many.
+    fn balance(&mut self) {
+        if self.lir > self.lir_cap {
+            let idx = self.stack.front;
+            self.unlink_stack(idx);
+            self.nodes[idx].state = LIRS_HIR;
+            self.queue.push(&mut self.nodes, lirs_queue, idx);
+            self.lir -= 1;
+            self.prune();
+        }
+    }
+
+    fn promote(&mut self, idx: usize) {
+        self.nodes[idx].state = LIRS_LIR;
+        self.lir += 1;
+        self.push_stack(idx);
+        self.balance();
+    }
+
+    // Caches `page` in the empty `frame`, as LIR if it was a ghost or there is room for one.
+    fn place(&mut self, frame: usize, page: u64) {
+        self.nodes[frame].page = page;
+        let ghost = match self.ghosts.get(&page) {
+            Some(ghost) => *ghost,
+            None => NULL,
+        };
+        if ghost != NULL {
+            self.drop_ghost(ghost);
+            self.prune();
+            self.promote(frame);
+        } else if self.lir < self.lir_cap {
+            self.promote(frame);
+        } else {
+            self.nodes[frame].state = LIRS_HIR;
+            self.push_stack(frame);
+            self.queue.push(&mut self.nodes, lirs_queue, frame);
+        }
+    }
+
+    // Evicts the front of the resident HIR queue, keeping its page on the stack as a ghost.
+    fn evict(&mut self) -> usize {
+        let frame = self.queue.front;
+        self.queue.unlink(&mut self.nodes, lirs_queue, frame);
+        if self.nodes[frame].in_stack {
+            if self.free_ghosts.is_empty() {
+                self.drop_ghost(self.ghost_queue.front);
+            }
+            let ghost = self.free_ghosts.pop().unwrap();
+            let page = self.nodes[frame].page;
+            self.stack.swap(&mut self.nodes, lirs_stack, frame, ghost);
+            self.nodes[frame].in_stack = false;
+            self.nodes[ghost].in_stack = true;
+            self.nodes[ghost].page = page;
+            self.nodes[ghost].state = LIRS_GHOST;
+            self.ghost_queue.push(&mut self.nodes, lirs_queue, ghost);
+            self.ghosts.insert(page, ghost);
+        }
+        frame
+    }
+}
+
+impl<Ratio: ConstF32> Replace for FALIRS<Ratio> {
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize =
+        (std::mem::size_of::<LIRSNode>() + std::mem::size_of::<usize>()) * 2
+            + std::mem::size_of::<(u64, usize)>();
+
+    fn new(count: usize) -> Self {
+        let node = LIRSNode {
+            page: u64::MAX,
+            stack: LIRS_UNLINKED,
+            queue: LIRS_UNLINKED,
+            in_stack: false,
+            state: LIRS_FREE,
+        };
+        // At least one frame is left for resident HIR pages to cycle through.
+        let lir_cap = std::cmp::min(
+            std::cmp::max((count as f32 * Ratio::VALUE) as usize, 1),
+            count.saturating_sub(1),
+        );
+        Self {
+            nodes: vec![node; count * 2],
+            ghosts: std::collections::HashMap::with_capacity(count),
+            stack: LIRSChain::new(),
+            queue: LIRSChain::new(),
+            ghost_queue: LIRSChain::new(),
+            free: (0..count).rev().collect(),
+            free_ghosts: (count..(count * 2)).rev().collect(),
+            lir: 0,
+            lir_cap,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    fn replace(&mut self, page: u64) -> usize {
+        let frame = match self.free.pop() {
+            Some(frame) => frame,
+            None => self.evict(),
+        };
+        self.place(frame, page);
+        frame
+    }
+
+    fn record_insert(&mut self, idx: usize, page: u64) {
+        if self.nodes[idx].state == LIRS_FREE {
+            let pos = self.free.iter().rposition(|f| *f == idx).unwrap();
+            self.free.remove(pos);
+            self.place(idx, page);
+        }
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        match self.nodes[idx].state {
+            LIRS_LIR => {
+                self.unlink_stack(idx);
+                self.push_stack(idx);
+                self.prune();
+            }
+            LIRS_HIR if self.nodes[idx].in_stack => {
+                self.unlink_stack(idx);
+                self.queue.unlink(&mut self.nodes, lirs_queue, idx);
+                self.promote(idx);
+            }
+            LIRS_HIR => {
+                self.push_stack(idx);
+                self.queue.unlink(&mut self.nodes, lirs_queue, idx);
+                self.queue.push(&mut self.nodes, lirs_queue, idx);
+            }
+            _ => {}
+        }
+    }
+
+    fn record_remove(&mut self, idx: usize) {
+        match self.nodes[idx].state {
+            LIRS_LIR => {
+                self.unlink_stack(idx);
+                self.lir -= 1;
+                self.prune();
+            }
+            LIRS_HIR => {
+                self.queue.unlink(&mut self.nodes, lirs_queue, idx);
+                if self.nodes[idx].in_stack {
+                    self.unlink_stack(idx);
+                }
+            }
+            _ => return,
+        }
+        self.nodes[idx].page = u64::MAX;
+        self.nodes[idx].state = LIRS_FREE;
+        self.free.push(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn second_chance<R: Replace>(mut r: R) {
+        assert_eq!(r.replace(10), 0);
+        assert_eq!(r.replace(11), 1);
+        r.record_access(2);
+        r.record_access(0);
+        assert_eq!(r.replace(13), 3);
+        assert_eq!(r.replace(11), 1);
+        assert_eq!(r.replace(12), 2);
+    }
+
+    fn scan_resistant<R: Replace>(mut r: R) {
+        for page in 0..4 {
+            assert_eq!(r.replace(page), page as usize);
+        }
+        r.record_access(0);
+        r.record_access(1);
+        for page in 100..200 {
+            let frame = r.replace(page);
+            assert!(frame >= 2);
+        }
+    }
+
+    #[test]
+    fn arc() {
+        scan_resistant(ARC::<Arr<ARCMeta, 8>>::new(4));
+        scan_resistant(FAARC::new(4));
+
+        let mut r = FAARC::new(4);
+        for page in 0..4 {
+            r.replace(page);
+        }
+        r.record_access(1);
+        r.replace(4);
+        let frame = r.replace(0);
+        assert_eq!(r.state.target, 1);
+        assert_eq!(r.nodes[frame].list, ARC_T2);
+        assert_eq!(r.state.len(ARC_B1), 1);
+    }
+
+    // Replays `trace` against `r`, returning the number of hits.
+    fn hits<R: Replace>(mut r: R, count: usize, trace: impl Iterator<Item = u64>) -> usize {
+        let mut frames = vec![u64::MAX; count];
+        let mut hits = 0;
+        for page in trace {
+            match frames.iter().position(|p| *p == page) {
+                Some(frame) => {
+                    r.record_access(frame);
+                    hits += 1;
+                }
+                None => frames[r.replace(page)] = page,
+            }
+        }
+        hits
+    }
+
+    struct Half;
+
+    impl ConstF32 for Half {
+        const VALUE: f32 = 0.5;
+    }
+
+    #[test]
+    fn slru() {
+        scan_resistant(SLRU::<Arr<SLRUMeta, 4>, Half>::new(4));
+        scan_resistant(FASLRU::<Half>::new(4));
+
+        let mut r = FASLRU::<Half>::new(4);
+        for page in 0..4 {
+            r.replace(page);
+        }
+        r.record_access(0);
+        r.record_access(1);
+        r.record_access(2);
+        // 0 was demoted to probation behind 3 when 2 was protected.
+        assert_eq!(r.replace(4), 3);
+        assert_eq!(r.replace(5), 0);
+    }
+
+    #[test]
+    fn two_q() {
+        let mut r = FATwoQ::<Half>::new(4);
+        for page in 0..4 {
+            assert_eq!(r.replace(page), page as usize);
+        }
+        // Hits in A1in don't promote, so the FIFO order holds.
+        r.record_access(0);
+        assert_eq!(r.replace(4), 0);
+        // 0 is in A1out now, missing it again promotes it to Am.
+        let frame = r.replace(0);
+        assert_eq!(frame, 1);
+        assert_eq!(r.nodes[frame].list, TWOQ_AM);
+        assert_eq!(r.replace(5), 2);
+
+        let mut r = TwoQ::<Usize<4>, Arr<TwoQMeta, 6>, Half>::new(4);
+        for page in 0..5 {
+            r.replace(page);
+        }
+        let frame = r.replace(0);
+        assert_eq!(r.nodes.get_ref()[frame].list, TWOQ_AM);
+    }
+
+    #[test]
+    fn s3fifo() {
+        scan_resistant(S3FIFO::<Usize<4>, Arr<S3FIFOMeta, 8>>::new(4));
+        scan_resistant(FAS3FIFO::new(4));
+
+        let mut r = FAS3FIFO::new(10);
+        for page in 0..10 {
+            r.replace(page);
+        }
+        r.record_access(0);
+        // 0 was accessed in the small FIFO and moves to the main one, 1 is evicted.
+        assert_eq!(r.replace(10), 1);
+        assert_eq!(r.nodes[0].list, S3_MAIN);
+        assert_eq!(r.nodes[r.ghosts[&1]].list, S3_GHOST);
+        // Missing 1 again puts it straight into the main FIFO.
+        let frame = r.replace(1);
+        assert_eq!(frame, 2);
+        assert_eq!(r.nodes[frame].list, S3_MAIN);
+        assert!(!r.ghosts.contains_key(&1));
+
+        // With a single ghost slot, the ghost of the missed page is also the one evicting would
+        // drop.
+        let mut r = S3FIFO::<Usize<4>, Arr<S3FIFOMeta, 5>>::new(4);
+        for page in 0..6 {
+            r.replace(page);
+        }
+        let frame = r.replace(1);
+        assert_eq!(r.nodes.get_ref()[frame].list, S3_MAIN);
+    }
+
+    struct MostlyLIR;
+
+    impl ConstF32 for MostlyLIR {
+        const VALUE: f32 = 0.9;
+    }
+
+    #[test]
+    fn lirs() {
+        scan_resistant(FALIRS::<Half>::new(4));
+
+        // A loop one page larger than the cache: LRU never hits, LIRS keeps the LIR pages.
+        let trace = || (0..100).flat_map(|_| 0..11);
+        assert_eq!(hits(FALRU::new(10), 10, trace()), 0);
+        assert!(hits(FALIRS::<MostlyLIR>::new(10), 10, trace()) > 800);
+
+        // A HIR page reused while on the stack becomes LIR and demotes the bottom LIR page.
+        let mut r = FALIRS::<Half>::new(4);
+        for page in 0..4 {
+            r.replace(page);
+        }
+        r.record_access(3);
+        assert_eq!(r.nodes[3].state, LIRS_LIR);
+        assert_eq!(r.nodes[0].state, LIRS_HIR);
+        assert_eq!(r.lir, 2);
+        // 2 is evicted but stays on the stack, so missing it again makes it LIR.
+        assert_eq!(r.replace(4), 2);
+        assert_eq!(r.nodes[r.ghosts[&2]].state, LIRS_GHOST);
+        let frame = r.replace(2);
+        assert_eq!(r.nodes[frame].state, LIRS_LIR);
+        assert!(!r.ghosts.contains_key(&2));
+    }
+
+    #[test]
+    fn plru() {
+        let mut r = TreePLRU::<Usize<4>>::new(4);
+        let fill: Vec<usize> = (0..4).map(|page| r.replace(page)).collect();
+        assert_eq!(fill, vec![0, 2, 1, 3]);
+        r.record_access(0);
+        assert_eq!(r.replace(4), 2);
+        let mut r = TreePLRU::<Usize<16>>::new(16);
+        for way in 0..16 {
+            r.record_access(way);
+            assert_ne!(r.replace(100), way);
+        }
+
+        let mut r = BitPLRU::<Usize<4>>::new(4);
+        for page in 0..4 {
+            assert_eq!(r.replace(page), page as usize);
+        }
+        r.record_access(0);
+        assert_eq!(r.replace(4), 1);
+        assert_eq!(r.replace(5), 2);
+        let mut r = NRU::<Usize<64>>::new(64);
+        for page in 0..64 {
+            assert_eq!(r.replace(page), page as usize);
+        }
+        r.record_remove(40);
+        r.record_remove(3);
+        assert_eq!(r.replace(64), 3);
+        assert_eq!(r.replace(65), 40);
+
+        // The policy bits and the free frames are all the state there is.
+        assert_eq!(std::mem::size_of::<TreePLRU<Usize<64>>>(), 16);
+        assert_eq!(std::mem::size_of::<BitPLRU<Usize<64>>>(), 16);
+    }
+
+    #[test]
+    fn rrip() {
+        let mut r = SRRIP::<Arr<u8, 4>, Usize<2>>::new(4);
+        for page in 0..4 {
+            assert_eq!(r.replace(page), page as usize);
+        }
+        r.record_access(0);
+        assert_eq!(r.replace(4), 1);
+        assert_eq!(r.rrpv.get_ref(), &[1, 2, 3, 3]);
+
+        // A loop over twice the ways thrashes LRU, BRRIP keeps part of it resident.
+        let trace = || (0..100).flat_map(|_| 0..8);
+        assert_eq!(hits(LRU::<Arr<LRUMeta, 4>>::new(4), 4, trace()), 0);
+        assert!(hits(BRRIP::<Arr<u8, 4>, Usize<2>>::new(4), 4, trace()) > 200);
+
+        let duel = std::sync::Arc::new(SetDuel::new());
+        let mut sets: Vec<DRRIP<Arr<u8, 4>, Usize<2>>> = (0..64)
+            .map(|idx| {
+                let mut r = DRRIP::new(4);
+                r.join_duel(idx, 64, &duel);
+                r
+            })
+            .collect();
+        assert_eq!(sets[0].seat.role, DuelRole::LeaderA);
+        assert_eq!(sets[33].seat.role, DuelRole::LeaderB);
+        assert_eq!(sets[2].seat.role, DuelRole::Follower);
+        let frame = sets[2].replace(0);
+        assert_eq!(sets[2].rrpv.get_ref()[frame], 2);
+        // The SRRIP leader missing more makes the followers insert like BRRIP.
+        for page in 0..10 {
+            sets[0].replace(page);
+        }
+        assert!(duel.prefers_b());
+        let frame = sets[2].replace(1);
+        assert_eq!(sets[2].rrpv.get_ref()[frame], 3);
+    }
+
+    #[test]
+    fn duel() {
+        type LRUOrFIFO = Duel<LRU<Arr<LRUMeta, 4>>, FIFO<Usize<4>>>;
+        let duel = std::sync::Arc::new(SetDuel::new());
+        let mut sets: Vec<LRUOrFIFO> = (0..64)
+            .map(|idx| {
+                let mut r = LRUOrFIFO::new(4);
+                r.join_duel(idx, 64, &duel);
+                r
+            })
+            .collect();
+        let follow = |r: &mut LRUOrFIFO| {
+            for page in 0..4 {
+                let frame = r.replace(page);
+                r.record_insert(frame, page);
+            }
+            r.record_access(0);
+            r.replace(4)
+        };
+        // Followers start out with LRU, which spares the frame just accessed.
+        assert_eq!(follow(&mut sets[2]), 1);
+        for page in 0..20 {
+            sets[32].replace(page);
+        }
+        assert!(duel.prefers_b());
+        assert_eq!(follow(&mut sets[3]), 0);
+        for page in 0..40 {
+            sets[33].replace(page);
+        }
+        assert!(!duel.prefers_b());
+    }
+
+    #[test]
+    fn duel_resets_other() {
+        type LRUOrLFU =
+            Duel<LRU<Arr<LRUMeta, 4>>, LFU<Arr<HeapElem<LFUMeta>, 4>, Arr<usize, 4>, NoAging>>;
+        let duel = std::sync::Arc::new(SetDuel::new());
+        let mut sets: Vec<LRUOrLFU> = (0..64)
+            .map(|idx| {
+                let mut r = LRUOrLFU::new(4);
+                r.join_duel(idx, 64, &duel);
+                r
+            })
+            .collect();
+        let r = &mut sets[2];
+        for page in 0..4 {
+            let frame = r.replace(page);
+            r.record_insert(frame, page);
+        }
+        for _ in 0..20 {
+            r.record_access(0);
+        }
+        for frame in 1..4 {
+            r.record_access(frame);
+        }
+        // LRU evicts the hot page; LFU must not credit its count to the new one.
+        assert_eq!(r.replace(4), 0);
+        r.record_insert(0, 4);
+        for page in 0..20 {
+            sets[32].replace(page);
+        }
+        assert!(duel.prefers_b());
+        assert_eq!(sets[2].replace(5), 0);
+    }
+
+    // Empties some frames of a full, recently accessed set and expects them to be used next.
+    fn frees_first<R: Replace>(mut r: R, count: usize) {
+        for page in 0..count as u64 {
+            let frame = r.replace(page);
+            r.record_insert(frame, page);
+        }
+        for frame in 0..count {
+            r.record_access(frame);
+        }
+        let mut free = vec![1, count / 2, count - 1];
+        for frame in free.iter() {
+            r.record_remove(*frame);
+        }
+        let mut used: Vec<usize> = (100..103)
+            .map(|page| {
+                let frame = r.replace(page);
+                r.record_insert(frame, page);
+                frame
+            })
+            .collect();
+        free.sort_unstable();
+        used.sort_unstable();
+        assert_eq!(used, free);
+    }
+
+    #[test]
+    fn lifecycle() {
+        frees_first(Random::<Usize<4>, XorShift<Usize<1>>>::new(4), 4);
+        frees_first(FARandom::<XorShift<Usize<1>>>::new(8), 8);
+        frees_first(LRU::<Arr<LRUMeta, 4>>::new(4), 4);
+        frees_first(FALRU::new(4), 4);
+        frees_first(
+            LFU::<Arr<HeapElem<LFUMeta>, 4>, Arr<usize, 4>, NoAging>::new(4),
+            4,
+        );
+        frees_first(FALFU::<OpsAging<Usize<3>>>::new(8), 8);
+        frees_first(
+            LRFU::<Arr<HeapElem<LRFUMeta>, 4>, Arr<usize, 4>, Half>::new(4),
+            4,
+        );
+        frees_first(FALRFU::<Half>::new(8), 8);
+        frees_first(AdaptiveLRFU::<FALRFU<Half>>::new(8), 8);
+        frees_first(FIFO::<Usize<4>>::new(4), 4);
+        frees_first(FAFIFO::new(4), 4);
+        frees_first(Clock::<Usize<4>, Arr<u64, 1>>::new(4), 4);
+        frees_first(FAClock::new(4), 4);
+        frees_first(TreePLRU::<Usize<8>>::new(8), 8);
+        frees_first(BitPLRU::<Usize<4>>::new(4), 4);
+        frees_first(SRRIP::<Arr<u8, 4>, Usize<2>>::new(4), 4);
+        frees_first(BRRIP::<Arr<u8, 4>, Usize<2>>::new(4), 4);
+        frees_first(DRRIP::<Arr<u8, 4>, Usize<2>>::new(4), 4);
+        frees_first(ARC::<Arr<ARCMeta, 8>>::new(4), 4);
+        frees_first(FAARC::new(4), 4);
+        frees_first(SLRU::<Arr<SLRUMeta, 4>, Half>::new(4), 4);
+        frees_first(FASLRU::<Half>::new(4), 4);
+        frees_first(TwoQ::<Usize<4>, Arr<TwoQMeta, 6>, Half>::new(4), 4);
+        frees_first(FATwoQ::<Half>::new(4), 4);
+        frees_first(S3FIFO::<Usize<4>, Arr<S3FIFOMeta, 8>>::new(4), 4);
+        frees_first(FAS3FIFO::new(4), 4);
+        frees_first(FALIRS::<Half>::new(4), 4);
+        frees_first(
+            GreedyDual::<Arr<HeapElem<GDMeta>, 4>, Arr<usize, 4>>::new(4),
+            4,
+        );
+        frees_first(FAGreedyDual::new(4), 4);
+        frees_first(CFLRU::<Arr<LRUMeta, 4>, Arr<bool, 4>, Half>::new(4), 4);
+        frees_first(FACFLRU::<Half>::new(4), 4);
+        frees_first(Duel::<FALRU, FALFU<NoAging>>::new(4), 4);
+        frees_first(TinyLFU::<FALRU>::new(4), 4);
+        frees_first(TinyLFU::<FAClock>::new(8), 8);
+    }
+
+    #[test]
+    fn lfu() {
+        let mut r = LFU::<Arr<HeapElem<LFUMeta>, 3>, Arr<usize, 3>, NoAging>::new(3);
+        assert_eq!(picks(&mut r, 3), vec![0, 1, 2]);
+        // Equal counts evict the least recently used page.
+        for frame in [2, 0, 1].iter() {
+            r.record_access(*frame);
+        }
+        assert_eq!(r.replace(3), 2);
+        r.record_access(2);
+        assert_eq!(r.replace(4), 0);
+
+        // Without aging a page hot long ago stays forever, with aging it eventually goes.
+        fn hot_then_cold<R: Replace>(mut r: R) -> bool {
+            picks(&mut r, 2);
+            for _ in 0..6 {
+                r.record_access(0);
+            }
+            (0..50).any(|page| r.replace(page) == 0)
+        }
+        assert!(!hot_then_cold(FALFU::<NoAging>::new(2)));
+        assert!(hot_then_cold(FALFU::<OpsAging<Usize<8>>>::new(2)));
+        assert!(hot_then_cold(LFU::<
+            Arr<HeapElem<LFUMeta>, 2>,
+            Arr<usize, 2>,
+            OpsAging<Usize<8>>,
+        >::new(2)));
+    }
+
+    #[test]
+    fn lfu_heap() {
+        let mut r = FALFU::<OpsAging<Usize<5>>>::new(7);
+        let mut x = 1u64;
+        for page in 0..500 {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            match (x >> 33) % 3 {
+                0 => {
+                    r.replace(page);
+                }
+                1 => r.record_access((x >> 40) as usize % 7),
+                _ => r.record_remove((x >> 40) as usize % 7),
+            }
+            let heap = &r.heap;
+            for (pos, frame) in heap.queue.iter().enumerate() {
+                assert_eq!(heap.data[*frame].pos, pos);
+                if pos > 0 {
+                    let parent = &heap.data[heap.queue[(pos - 1) / 2]].data;
+                    assert!(lfu_cmp(parent, &heap.data[*frame].data) <= 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lrfu() {
+        // A page idle long enough for its CRF to underflow still ranks above an empty frame.
+        let mut r = FALRFU::<Half>::new(3);
+        picks(&mut r, 3);
+        for _ in 0..5000 {
+            r.record_access(1);
+            r.record_access(2);
+        }
+        r.record_remove(2);
+        assert_eq!(r.replace(3), 2);
+        assert_eq!(r.replace(4), 0);
+
+        // Pages accessed often long ago beat ones accessed once recently as the rate nears 1.
+        fn often_then_once<R: Replace + LRFURate>(mut r: R, rate: f32) -> usize {
+            picks(&mut r, 2);
+            for _ in 0..4 {
+                r.record_access(0);
+            }
+            r.record_access(1);
+            r.set_rate(rate);
+            r.replace(2)
+        }
+        type Fixed = LRFU<Arr<HeapElem<LRFUMeta>, 2>, Arr<usize, 2>, Half>;
+        assert_eq!(often_then_once(Fixed::new(2), 0.1), 0);
+        assert_eq!(often_then_once(Fixed::new(2), 0.99), 1);
+        assert_eq!(often_then_once(FALRFU::<Half>::new(2), 0.1), 0);
+        assert_eq!(often_then_once(FALRFU::<Half>::new(2), 0.99), 1);
+    }
+
+    #[test]
+    fn adaptive_lrfu() {
+        fn ghost_hit(accesses: usize) -> f32 {
+            let mut r = AdaptiveLRFU::<FALRFU<Half>>::new(1);
+            for page in [0, 1, 0].iter() {
+                let frame = r.replace(*page);
+                r.record_insert(frame, *page);
+                for _ in 0..accesses {
+                    r.record_access(frame);
+                }
+            }
+            r.rate()
+        }
+        assert!(ghost_hit(0) < 0.5);
+        assert!(ghost_hit(3) > 0.5);
+    }
+
+    fn picks<R: Replace>(r: &mut R, n: usize) -> Vec<usize> {
+        (0..n).map(|page| r.replace(page as u64)).collect()
+    }
+
+    #[test]
+    fn random() {
+        let mut r = Random::<Usize<3>, XorShift<Usize<7>>>::new(3);
+        let mut counts = [0; 3];
+        for frame in picks(&mut r, 3000) {
+            counts[frame] += 1;
+        }
+        assert!(
+            counts.iter().all(|c| (900..1100).contains(c)),
+            "{:?}",
+            counts
+        );
+
+        // Streams are reproducible, but differ between sets and seeds.
+        let mut a = FARandom::<XorShift<Usize<7>>>::new(5);
+        let mut b = FARandom::<XorShift<Usize<7>>>::new(5);
+        assert_eq!(picks(&mut a, 32), picks(&mut b, 32));
+        let mut c = FARandom::<XorShift<Usize<8>>>::new(5);
+        assert_ne!(picks(&mut a, 32), picks(&mut c, 32));
+        let mut d = Random::<Usize<5>, XorShift<Usize<7>>>::new(5);
+        let mut e = Random::<Usize<5>, XorShift<Usize<7>>>::new(5);
+        d.join_set(0, 2);
+        e.join_set(1, 2);
+        assert_ne!(picks(&mut d, 32), picks(&mut e, 32));
+        assert!(picks(&mut d, 100).iter().all(|frame| *frame < 5));
+    }
+
+    fn clean_first<R: Replace>(mut r: R) {
+        for page in 0..4 {
+            let frame = r.replace(page);
+            r.record_insert(frame, page);
+        }
+        r.record_dirty(0, true);
+        assert_eq!(r.replace(4), 1);
+        r.record_insert(1, 4);
+        // Dirty frames are only taken when the whole window is dirty.
+        r.record_dirty(2, true);
+        assert_eq!(r.replace(5), 0);
+        r.record_insert(0, 5);
+        r.record_dirty(2, false);
+        assert_eq!(r.replace(6), 2);
+    }
+
+    #[test]
+    fn cflru() {
+        clean_first(CFLRU::<Arr<LRUMeta, 4>, Arr<bool, 4>, Half>::new(4));
+        clean_first(FACFLRU::<Half>::new(4));
+    }
+
+    fn insert_costly<R: Replace>(r: &mut R, page: u64, cost: u64) -> usize {
+        let frame = r.replace(page);
+        r.record_insert(frame, page);
+        r.record_cost(frame, cost);
+        frame
+    }
+
+    fn cheap_first<R: Replace>(mut r: R) {
+        assert_eq!(insert_costly(&mut r, 0, 10), 0);
+        assert_eq!(insert_costly(&mut r, 1, 1), 1);
+        assert_eq!(insert_costly(&mut r, 2, 6), 2);
+        // Cheap pages make room first, each victim raising the worth of new pages.
+        assert_eq!(insert_costly(&mut r, 3, 2), 1);
+        assert_eq!(insert_costly(&mut r, 4, 2), 1);
+        assert_eq!(insert_costly(&mut r, 5, 2), 1);
+        // An expensive page ages out once it isn't accessed, one that is stays.
+        assert_eq!(insert_costly(&mut r, 6, 2), 2);
+        r.record_access(0);
+        for page in 7..11 {
+            assert_ne!(insert_costly(&mut r, page, 2), 0);
+        }
+    }
+
+    #[test]
+    fn greedy_dual() {
+        cheap_first(GreedyDual::<Arr<HeapElem<GDMeta>, 3>, Arr<usize, 3>>::new(
+            3,
+        ));
+        cheap_first(FAGreedyDual::new(3));
+    }
+
+    #[test]
+    fn clock() {
+        second_chance(Clock::<Usize<4>, Arr<u64, 1>>::new(4));
+        second_chance(FAClock::new(4));
+    }
+
+    #[test]
+    fn fifo() {
+        let mut r = FIFO::<Usize<6>>::new(6);
+        let frames: Vec<usize> = (0..12).map(|page| r.replace(page)).collect();
+        assert_eq!(frames, vec![0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn free_frames_mem() {
+        // 100 frames take two words of bitmap on the heap.
+        assert_eq!(
+            FIFO::<Usize<100>>::STATIC_META_MEM,
+            std::mem::size_of::<FIFO<Usize<100>>>() + 16
+        );
+        assert_eq!(
+            SRRIP::<Arr<u8, 100>, Usize<2>>::STATIC_META_MEM,
+            std::mem::size_of::<SRRIP<Arr<u8, 100>, Usize<2>>>() - 100 + 16
+        );
+        // One bit per frame, rounded up to a byte.
+        assert_eq!(FAFIFO::META_MEM_PER_BLOCK, 1);
+        assert_eq!(FAClock::META_MEM_PER_BLOCK, 1);
+        assert_eq!(FARandom::<XorShift<Usize<1>>>::META_MEM_PER_BLOCK, 1);
+    }
 }
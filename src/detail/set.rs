@@ -1,5 +1,22 @@
 use super::*;
 
+const NIL: u64 = u64::MAX;
+
+#[derive(Clone, Copy)]
+pub struct BlockMeta {
+    pub page: u64,
+    pub dirty: bool,
+}
+
+impl Default for BlockMeta {
+    fn default() -> Self {
+        Self {
+            page: NIL,
+            dirty: false,
+        }
+    }
+}
+
 pub trait Set {
     type L: Lookup;
     type R: Replace;
@@ -11,37 +28,56 @@ pub trait Set {
     fn lookup_mut(&mut self) -> &mut Self::L;
     fn replace(&self) -> &Self::R;
     fn replace_mut(&mut self) -> &mut Self::R;
+    fn count(&self) -> usize;
     fn block(&self, idx: usize) -> &[u8];
     fn block_mut(&mut self, idx: usize) -> &mut [u8];
+    fn meta(&self, idx: usize) -> &BlockMeta;
+    fn meta_mut(&mut self, idx: usize) -> &mut BlockMeta;
 }
 
-pub struct NWaySet<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>> {
+pub struct NWaySet<
+    L: Lookup,
+    R: Replace,
+    Block: Array<u8>,
+    Blocks: Array<Block>,
+    Meta: Array<BlockMeta>,
+> {
     blocks: Blocks,
+    meta: Meta,
     lookup: L,
     replace: R,
     _marker: std::marker::PhantomData<Block>,
 }
 
-impl<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>> NWaySet<L, R, Block, Blocks> {
+impl<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>, Meta: Array<BlockMeta>>
+    NWaySet<L, R, Block, Blocks, Meta>
+{
     fn new() -> Self {
         Self {
             blocks: Blocks::new(),
-            lookup: L::new(0),
-            replace: R::new(0),
+            meta: Meta::new(),
+            lookup: L::new(Blocks::LEN),
+            replace: R::new(Blocks::LEN),
             _marker: std::marker::PhantomData,
         }
     }
 }
 
-impl<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>> Set
-    for NWaySet<L, R, Block, Blocks>
+impl<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>, Meta: Array<BlockMeta>> Set
+    for NWaySet<L, R, Block, Blocks, Meta>
 {
     type L = L;
     type R = R;
 
-    const STATIC_META_MEM: usize =
-        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK + Block::LEN);
-    const META_MEM_PER_BLOCK: usize = L::META_MEM_PER_BLOCK + R::META_MEM_PER_BLOCK;
+    const STATIC_META_MEM: usize = L::STATIC_META_MEM
+        + R::STATIC_META_MEM
+        + (std::mem::size_of::<Self>()
+            - std::mem::size_of::<L>()
+            - std::mem::size_of::<R>()
+            - std::mem::size_of::<Blocks>()
+            - std::mem::size_of::<Meta>());
+    const META_MEM_PER_BLOCK: usize =
+        L::META_MEM_PER_BLOCK + R::META_MEM_PER_BLOCK + std::mem::size_of::<BlockMeta>();
 
     fn lookup(&self) -> &Self::L {
         &self.lookup
@@ -59,6 +95,10 @@ impl<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>> Set
         &mut self.replace
     }
 
+    fn count(&self) -> usize {
+        Blocks::LEN
+    }
+
     fn block(&self, idx: usize) -> &[u8] {
         self.blocks.get_ref()[idx].get_ref()
     }
@@ -66,10 +106,19 @@ impl<L: Lookup, R: Replace, Block: Array<u8>, Blocks: Array<Block>> Set
     fn block_mut(&mut self, idx: usize) -> &mut [u8] {
         self.blocks.get_mut()[idx].get_mut()
     }
+
+    fn meta(&self, idx: usize) -> &BlockMeta {
+        &self.meta.get_ref()[idx]
+    }
+
+    fn meta_mut(&mut self, idx: usize) -> &mut BlockMeta {
+        &mut self.meta.get_mut()[idx]
+    }
 }
 
 pub struct DirectMappedSet<Block: Array<u8>> {
     block: Block,
+    meta: BlockMeta,
     lookup: DMLookup,
     replace: DMReplace,
 }
@@ -78,6 +127,7 @@ impl<Block: Array<u8>> DirectMappedSet<Block> {
     fn new() -> Self {
         Self {
             block: Block::new(),
+            meta: BlockMeta::default(),
             lookup: DMLookup::new(0),
             replace: DMReplace::new(0),
         }
@@ -88,8 +138,9 @@ impl<Block: Array<u8>> Set for DirectMappedSet<Block> {
     type L = DMLookup;
     type R = DMReplace;
 
-    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
-    const META_MEM_PER_BLOCK: usize = 0;
+    const STATIC_META_MEM: usize =
+        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK + Block::LEN);
+    const META_MEM_PER_BLOCK: usize = std::mem::size_of::<BlockMeta>();
 
     fn lookup(&self) -> &Self::L {
         &self.lookup
@@ -107,6 +158,10 @@ impl<Block: Array<u8>> Set for DirectMappedSet<Block> {
         &mut self.replace
     }
 
+    fn count(&self) -> usize {
+        1
+    }
+
     fn block(&self, _: usize) -> &[u8] {
         self.block.get_ref()
     }
@@ -114,10 +169,19 @@ impl<Block: Array<u8>> Set for DirectMappedSet<Block> {
     fn block_mut(&mut self, _: usize) -> &mut [u8] {
         self.block.get_mut()
     }
+
+    fn meta(&self, _: usize) -> &BlockMeta {
+        &self.meta
+    }
+
+    fn meta_mut(&mut self, _: usize) -> &mut BlockMeta {
+        &mut self.meta
+    }
 }
 
 pub struct FullyAssociativeSet<L: Lookup, R: Replace, Block: Array<u8>> {
     blocks: Vec<Block>,
+    meta: Vec<BlockMeta>,
     lookup: L,
     replace: R,
 }
@@ -126,6 +190,7 @@ impl<L: Lookup, R: Replace, Block: Array<u8>> FullyAssociativeSet<L, R, Block> {
     fn new(count: usize) -> Self {
         Self {
             blocks: vec![Block::new(); count],
+            meta: vec![BlockMeta::default(); count],
             lookup: L::new(count),
             replace: R::new(count),
         }
@@ -136,9 +201,9 @@ impl<L: Lookup, R: Replace, Block: Array<u8>> Set for FullyAssociativeSet<L, R,
     type L = L;
     type R = R;
 
-    const STATIC_META_MEM: usize =
-        std::mem::size_of::<Self>() - (Self::META_MEM_PER_BLOCK + Block::LEN);
-    const META_MEM_PER_BLOCK: usize = L::META_MEM_PER_BLOCK + R::META_MEM_PER_BLOCK;
+    const STATIC_META_MEM: usize = std::mem::size_of::<Self>();
+    const META_MEM_PER_BLOCK: usize =
+        L::META_MEM_PER_BLOCK + R::META_MEM_PER_BLOCK + std::mem::size_of::<BlockMeta>();
 
     fn lookup(&self) -> &Self::L {
         &self.lookup
@@ -156,6 +221,10 @@ impl<L: Lookup, R: Replace, Block: Array<u8>> Set for FullyAssociativeSet<L, R,
         &mut self.replace
     }
 
+    fn count(&self) -> usize {
+        self.blocks.len()
+    }
+
     fn block(&self, idx: usize) -> &[u8] {
         self.blocks[idx].get_ref()
     }
@@ -163,6 +232,14 @@ impl<L: Lookup, R: Replace, Block: Array<u8>> Set for FullyAssociativeSet<L, R,
     fn block_mut(&mut self, idx: usize) -> &mut [u8] {
         self.blocks[idx].get_mut()
     }
+
+    fn meta(&self, idx: usize) -> &BlockMeta {
+        &self.meta[idx]
+    }
+
+    fn meta_mut(&mut self, idx: usize) -> &mut BlockMeta {
+        &mut self.meta[idx]
+    }
 }
 
 pub trait Sets {
@@ -173,6 +250,7 @@ pub trait Sets {
     fn new_strict(mem: usize) -> Self;
     fn count(&self) -> usize;
     fn set(&self, page: u64) -> &Self::IMS;
+    fn set_at(&self, idx: usize) -> &Self::IMS;
     fn data_mem(&self) -> usize;
     fn meta_mem(&self) -> usize;
 
@@ -186,13 +264,15 @@ pub struct NWaySets<
     R: Replace,
     Block: Array<u8>,
     Blocks: Array<Block>,
-    S: InnerMut<NWaySet<L, R, Block, Blocks>>,
+    Meta: Array<BlockMeta>,
+    S: InnerMut<NWaySet<L, R, Block, Blocks, Meta>>,
 > {
     sets: Vec<S>,
     _marker1: std::marker::PhantomData<L>,
     _marker2: std::marker::PhantomData<R>,
     _marker3: std::marker::PhantomData<Block>,
     _marker4: std::marker::PhantomData<Blocks>,
+    _marker5: std::marker::PhantomData<Meta>,
 }
 
 impl<
@@ -200,12 +280,32 @@ impl<
         R: Replace,
         Block: Array<u8>,
         Blocks: Array<Block>,
-        S: InnerMut<NWaySet<L, R, Block, Blocks>>,
-    > NWaySets<L, R, Block, Blocks, S>
+        Meta: Array<BlockMeta>,
+        S: InnerMut<NWaySet<L, R, Block, Blocks, Meta>>,
+    > NWaySets<L, R, Block, Blocks, Meta, S>
 {
     const MEM_PER_SET: usize = (Block::LEN * Blocks::LEN)
-        + NWaySet::<L, R, Block, Blocks>::STATIC_META_MEM
-        + (NWaySet::<L, R, Block, Blocks>::META_MEM_PER_BLOCK * Blocks::LEN);
+        + NWaySet::<L, R, Block, Blocks, Meta>::STATIC_META_MEM
+        + (NWaySet::<L, R, Block, Blocks, Meta>::META_MEM_PER_BLOCK * Blocks::LEN);
+
+    fn with_count(set_count: usize) -> Self {
+        let duel = std::sync::Arc::new(SetDuel::new());
+        let mut sets: Vec<S> = Vec::with_capacity(set_count);
+        for idx in 0..set_count {
+            let mut set = NWaySet::<L, R, Block, Blocks, Meta>::new();
+            set.replace.join_set(idx, set_count);
+            set.replace.join_duel(idx, set_count, &duel);
+            sets.push(S::new(set));
+        }
+        Self {
+            sets,
+            _marker1: std::marker::PhantomData,
+            _marker2: std::marker::PhantomData,
+            _marker3: std::marker::PhantomData,
+            _marker4: std::marker::PhantomData,
+            _marker5: std::marker::PhantomData,
+        }
+    }
 }
 
 impl<
@@ -213,42 +313,23 @@ impl<
         R: Replace,
         Block: Array<u8>,
         Blocks: Array<Block>,
-        S: InnerMut<NWaySet<L, R, Block, Blocks>>,
-    > Sets for NWaySets<L, R, Block, Blocks, S>
+        Meta: Array<BlockMeta>,
+        S: InnerMut<NWaySet<L, R, Block, Blocks, Meta>>,
+    > Sets for NWaySets<L, R, Block, Blocks, Meta, S>
 {
-    type S = NWaySet<L, R, Block, Blocks>;
+    type S = NWaySet<L, R, Block, Blocks, Meta>;
     type IMS = S;
 
     fn new(mem: usize) -> Self {
         let set_count = mem / (Blocks::LEN * Block::LEN);
-        let mut sets: Vec<S> = Vec::new();
-        sets.reserve(set_count);
-        for _ in 0..set_count {
-            sets.push(S::new(NWaySet::new()));
-        }
-        Self {
-            sets,
-            _marker1: std::marker::PhantomData,
-            _marker2: std::marker::PhantomData,
-            _marker3: std::marker::PhantomData,
-            _marker4: std::marker::PhantomData,
-        }
+        assert!(set_count > 0, "io_cache memory too small to hold one set");
+        Self::with_count(set_count)
     }
 
     fn new_strict(mem: usize) -> Self {
-        let set_count = (mem - std::mem::size_of::<Self>()) / Self::MEM_PER_SET;
-        let mut sets: Vec<S> = Vec::new();
-        sets.reserve(set_count);
-        for _ in 0..set_count {
-            sets.push(S::new(NWaySet::new()));
-        }
-        Self {
-            sets,
-            _marker1: std::marker::PhantomData,
-            _marker2: std::marker::PhantomData,
-            _marker3: std::marker::PhantomData,
-            _marker4: std::marker::PhantomData,
-        }
+        let set_count = mem.saturating_sub(std::mem::size_of::<Self>()) / Self::MEM_PER_SET;
+        assert!(set_count > 0, "io_cache memory too small to hold one set");
+        Self::with_count(set_count)
     }
 
     fn count(&self) -> usize {
@@ -259,14 +340,18 @@ impl<
         &self.sets[(page % self.sets.len() as u64) as usize]
     }
 
+    fn set_at(&self, idx: usize) -> &Self::IMS {
+        &self.sets[idx]
+    }
+
     fn data_mem(&self) -> usize {
         self.sets.len() * Blocks::LEN * Block::LEN
     }
 
     fn meta_mem(&self) -> usize {
         self.sets.len()
-            * (NWaySet::<L, R, Block, Blocks>::STATIC_META_MEM
-                + (NWaySet::<L, R, Block, Blocks>::META_MEM_PER_BLOCK * Blocks::LEN))
+            * (NWaySet::<L, R, Block, Blocks, Meta>::STATIC_META_MEM
+                + (NWaySet::<L, R, Block, Blocks, Meta>::META_MEM_PER_BLOCK * Blocks::LEN))
     }
 }
 
@@ -287,8 +372,8 @@ impl<Block: Array<u8>, S: InnerMut<DirectMappedSet<Block>>> Sets for DirectMappe
 
     fn new(mem: usize) -> Self {
         let set_count = mem / Block::LEN;
-        let mut sets: Vec<S> = Vec::new();
-        sets.reserve(set_count);
+        assert!(set_count > 0, "io_cache memory too small to hold one set");
+        let mut sets: Vec<S> = Vec::with_capacity(set_count);
         for _ in 0..set_count {
             sets.push(S::new(DirectMappedSet::new()));
         }
@@ -299,9 +384,9 @@ impl<Block: Array<u8>, S: InnerMut<DirectMappedSet<Block>>> Sets for DirectMappe
     }
 
     fn new_strict(mem: usize) -> Self {
-        let set_count = (mem - std::mem::size_of::<Self>()) / Self::MEM_PER_SET;
-        let mut sets: Vec<S> = Vec::new();
-        sets.reserve(set_count);
+        let set_count = mem.saturating_sub(std::mem::size_of::<Self>()) / Self::MEM_PER_SET;
+        assert!(set_count > 0, "io_cache memory too small to hold one set");
+        let mut sets: Vec<S> = Vec::with_capacity(set_count);
         for _ in 0..set_count {
             sets.push(S::new(DirectMappedSet::new()));
         }
@@ -319,6 +404,10 @@ impl<Block: Array<u8>, S: InnerMut<DirectMappedSet<Block>>> Sets for DirectMappe
         &self.sets[(page % self.sets.len() as u64) as usize]
     }
 
+    fn set_at(&self, idx: usize) -> &Self::IMS {
+        &self.sets[idx]
+    }
+
     fn data_mem(&self) -> usize {
         self.sets.len() * Block::LEN
     }
@@ -356,8 +445,10 @@ impl<L: Lookup, R: Replace, Block: Array<u8>, S: InnerMut<FullyAssociativeSet<L,
     type IMS = S;
 
     fn new(mem: usize) -> Self {
+        let count = mem / Block::LEN;
+        assert!(count > 0, "io_cache memory too small to hold one block");
         Self {
-            set: S::new(FullyAssociativeSet::new(mem / Block::LEN)),
+            set: S::new(FullyAssociativeSet::new(count)),
             _marker1: std::marker::PhantomData,
             _marker2: std::marker::PhantomData,
             _marker3: std::marker::PhantomData,
@@ -365,8 +456,10 @@ impl<L: Lookup, R: Replace, Block: Array<u8>, S: InnerMut<FullyAssociativeSet<L,
     }
 
     fn new_strict(mem: usize) -> Self {
+        let count = mem.saturating_sub(std::mem::size_of::<Self>()) / Self::MEM_PER_BLOCK;
+        assert!(count > 0, "io_cache memory too small to hold one block");
         Self {
-            set: S::new(FullyAssociativeSet::new(mem / Self::MEM_PER_BLOCK)),
+            set: S::new(FullyAssociativeSet::new(count)),
             _marker1: std::marker::PhantomData,
             _marker2: std::marker::PhantomData,
             _marker3: std::marker::PhantomData,
@@ -381,6 +474,10 @@ impl<L: Lookup, R: Replace, Block: Array<u8>, S: InnerMut<FullyAssociativeSet<L,
         &self.set
     }
 
+    fn set_at(&self, _idx: usize) -> &Self::IMS {
+        &self.set
+    }
+
     fn data_mem(&self) -> usize {
         self.set.read(|s| s.blocks.len()) * Block::LEN
     }
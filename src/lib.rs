@@ -1,36 +1,90 @@
+use std::io::Write;
 use std::ops::RangeBounds;
 
 pub mod config;
 pub mod detail;
+pub mod mrc;
+pub mod observer;
+pub mod prometheus;
+pub mod sim;
+pub mod stats;
 
 mod cache_impl;
 use cache_impl::CacheImpl;
+use detail::Writer;
 
+// Dirty blocks are written back when the cache is dropped. Call `flush` first to see whether that
+// worked.
 pub struct IOCache<Config: config::CacheConfig> {
-    cache: CacheImpl<Config::Source, Config::S>,
+    cache: CacheImpl<Config>,
 }
 
 impl<Config: config::CacheConfig> IOCache<Config> {
     // Panic if mem isn't enough to hold one set.
     // Panic if CacheConfig is an invalid configuration.
-    pub fn new(source: Config::Source, mem: usize) -> Self {
-        Self {
-            cache: CacheImpl::new(source, mem),
-        }
+    // Panic if the source can't be opened.
+    pub fn new(source: Config::Source, mem: usize) -> Self
+    where
+        Config::Observer: Default,
+    {
+        Self::new_with_observer(source, mem, Default::default())
     }
 
     // Panic if mem isn't enough to hold one set and meta data.
     // Panic if CacheConfig is an invalid configuration.
-    pub fn new_strict(source: Config::Source, mem: usize) -> Self {
+    // Panic if the source can't be opened.
+    pub fn new_strict(source: Config::Source, mem: usize) -> Self
+    where
+        Config::Observer: Default,
+    {
+        Self::new_strict_with_observer(source, mem, Default::default())
+    }
+
+    pub fn new_with_observer(
+        source: Config::Source,
+        mem: usize,
+        observer: Config::Observer,
+    ) -> Self {
         Self {
-            cache: CacheImpl::new_strict(source, mem),
+            cache: CacheImpl::new(source, mem, observer),
         }
     }
 
+    pub fn new_strict_with_observer(
+        source: Config::Source,
+        mem: usize,
+        observer: Config::Observer,
+    ) -> Self {
+        Self {
+            cache: CacheImpl::new_strict(source, mem, observer),
+        }
+    }
+
+    pub fn observer(&self) -> &Config::Observer {
+        self.cache.observer()
+    }
+
+    pub fn io_latency(&self) -> Option<&detail::IOLatency> {
+        self.cache.io_latency()
+    }
+
+    // Runs `f` on the replacement policy of every set, e.g. to retune it at run time.
+    pub fn for_each_replace<F: FnMut(&mut cache_impl::ReplaceOf<Config>)>(&self, f: F) {
+        self.cache.for_each_replace(f)
+    }
+
+    pub fn stats(&self) -> stats::CacheStats {
+        self.cache.stats()
+    }
+
     pub fn into_source(self) -> Config::Source {
         self.cache.into_inner()
     }
 
+    pub fn invalidate<R: RangeBounds<u64>>(&self, range: R) -> std::io::Result<()> {
+        self.cache.invalidate(range)
+    }
+
     pub fn read_chunks<R: RangeBounds<u64>, F: FnMut(&[u8])>(&self, range: R, f: F) {
         self.cache.read_chunks(range, f)
     }
@@ -38,10 +92,20 @@ impl<Config: config::CacheConfig> IOCache<Config> {
     pub fn read<R: RangeBounds<u64>>(&self, range: R, buf: &mut [u8]) -> usize {
         self.cache.read(range, buf)
     }
+}
 
-    pub fn write(&mut self, offset: u64, buf: &[u8]) -> usize {
+impl<Config: config::CacheConfig> IOCache<Config>
+where
+    Config::Source: Write,
+    Config::IO: Writer<Config::Source>,
+{
+    pub fn write(&self, offset: u64, buf: &[u8]) -> usize {
         self.cache.write(offset, buf)
     }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.cache.flush()
+    }
 }
 
 /*
@@ -55,8 +119,397 @@ cache_config! {
 
 #[cfg(test)]
 mod tests {
+    use super::config::CacheConfig;
+    use super::detail::*;
+    use super::observer::CacheObserver;
+    use super::IOCache;
+
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    type Block = Arr<u8, 16>;
+    type TestIO = SyncIO<Cursor<Vec<u8>>, Usize<16>, False>;
+
+    #[derive(Default)]
+    struct Counts {
+        hits: Cell<usize>,
+        misses: Cell<usize>,
+        evictions: Cell<usize>,
+        writebacks: Cell<usize>,
+    }
+
+    impl CacheObserver for Counts {
+        fn on_hit(&self, _: u64) {
+            self.hits.set(self.hits.get() + 1);
+        }
+
+        fn on_miss(&self, _: u64) {
+            self.misses.set(self.misses.get() + 1);
+        }
+
+        fn on_evict(&self, _: u64, _: bool) {
+            self.evictions.set(self.evictions.get() + 1);
+        }
+
+        fn on_writeback(&self, _: u64, result: &std::io::Result<()>) {
+            assert!(result.is_ok());
+            self.writebacks.set(self.writebacks.get() + 1);
+        }
+    }
+
+    // Sets of 4 ways of 16 bytes over an in-memory source, with the lookup, policy, I/O and
+    // observer under test.
+    struct Config<L, R, IO = TestIO, Observer = Counts>(
+        std::marker::PhantomData<(L, R, IO, Observer)>,
+    );
+
+    type TestSet<L, R> = NWaySet<L, R, Block, Arr<Block, 4>, Arr<BlockMeta, 4>>;
+
+    impl<L, R, IO, Observer> CacheConfig for Config<L, R, IO, Observer>
+    where
+        L: Lookup,
+        R: Replace,
+        IO: Reader<Cursor<Vec<u8>>>,
+        Observer: CacheObserver,
+    {
+        type Source = Cursor<Vec<u8>>;
+        type BlockSize = Usize<16>;
+        type Blocks = Block;
+        type WriteThrough = False;
+        type AsyncWrite = False;
+        type Associativity = Usize<4>;
+        type NWay = Usize<4>;
+        type BlocksPerFetch = Usize<1>;
+        type ThreadSafe = False;
+        type EnableStats = True;
+        type WrappedSource = RefCell<Self::Source>;
+        type IO = IO;
+        type Observer = Observer;
+        type S = NWaySets<L, R, Block, Arr<Block, 4>, Arr<BlockMeta, 4>, RefCell<TestSet<L, R>>>;
+    }
+
+    type ScanLookup = Scan<Arr<u64, 4>>;
+    type TestLRU = LRU<Arr<LRUMeta, 4>>;
+    type TestConfig = Config<ScanLookup, TestLRU>;
+    type AdmitConfig = Config<ScanLookup, TinyLFU<TestLRU>>;
+
+    // Pages 0 and 1 sit on a slow tier.
+    struct TieredIO(TestIO);
+
+    impl Reader<Cursor<Vec<u8>>> for TieredIO {
+        fn new(source: Cursor<Vec<u8>>) -> std::io::Result<Self> {
+            Ok(Self(SyncIO::new(source)?))
+        }
+
+        fn into_inner(self) -> Cursor<Vec<u8>> {
+            self.0.into_inner()
+        }
+
+        fn len(&self) -> u64 {
+            self.0.len()
+        }
+
+        fn read(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
+            self.0.read(page, block)
+        }
+
+        fn miss_cost(&self, page: u64, _: std::time::Duration) -> u64 {
+            if page < 2 {
+                1000
+            } else {
+                1
+            }
+        }
+    }
+
+    // Reads of page 3 fail, and so do write backs of page 0 and of pages too far out to fit in
+    // memory.
+    struct FlakyIO(TestIO);
+
+    impl Reader<Cursor<Vec<u8>>> for FlakyIO {
+        fn new(source: Cursor<Vec<u8>>) -> std::io::Result<Self> {
+            Ok(Self(SyncIO::new(source)?))
+        }
+
+        fn into_inner(self) -> Cursor<Vec<u8>> {
+            self.0.into_inner()
+        }
+
+        fn len(&self) -> u64 {
+            self.0.len()
+        }
+
+        fn read(&self, page: u64, block: &mut [u8]) -> std::io::Result<()> {
+            if page == 3 {
+                return Err(std::io::Error::other("bad block"));
+            }
+            self.0.read(page, block)
+        }
+
+        fn write_back(&self, page: u64, block: &[u8]) -> std::io::Result<()> {
+            self.write(page, block)
+        }
+    }
+
+    impl Writer<Cursor<Vec<u8>>> for FlakyIO {
+        fn write(&self, page: u64, block: &[u8]) -> std::io::Result<()> {
+            if page == 0 || page > u32::MAX as u64 {
+                return Err(std::io::Error::other("bad block"));
+            }
+            self.0.write(page, block)
+        }
+    }
+
+    // Shares its counts, so they can be checked once the cache is gone.
+    #[derive(Default)]
+    struct Errors {
+        writebacks: Rc<Cell<usize>>,
+        reads: Rc<Cell<usize>>,
+    }
+
+    impl CacheObserver for Errors {
+        fn on_writeback(&self, _: u64, result: &std::io::Result<()>) {
+            if result.is_err() {
+                self.writebacks.set(self.writebacks.get() + 1);
+            }
+        }
+
+        fn on_source_error(&self, _: u64, _: &std::io::Error) {
+            self.reads.set(self.reads.get() + 1);
+        }
+    }
+
+    type FlakyConfig = Config<ScanLookup, TestLRU, FlakyIO, Errors>;
+
+    type CostConfig =
+        Config<ScanLookup, GreedyDual<Arr<HeapElem<GDMeta>, 4>, Arr<usize, 4>>, TieredIO>;
+
+    struct Half;
+
+    impl ConstF32 for Half {
+        const VALUE: f32 = 0.5;
+    }
+
+    type CleanFirstConfig = Config<ScanLookup, CFLRU<Arr<LRUMeta, 4>, Arr<bool, 4>, Half>>;
+    type PredictConfig = Config<Predicted<ScanLookup, Usize<1>>, TestLRU>;
+
+    fn data() -> Vec<u8> {
+        (0..250).map(|b| b as u8).collect()
+    }
+
+    fn cache() -> IOCache<TestConfig> {
+        IOCache::new(Cursor::new(data()), 128)
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn read_through() {
+        let cache = cache();
+        let mut buf = [0; 40];
+        assert_eq!(cache.read(10..50, &mut buf), 40);
+        assert_eq!(&buf[..], &data()[10..50]);
+        assert_eq!(cache.observer().misses.get(), 4);
+        assert_eq!(cache.read(10..50, &mut buf), 40);
+        assert_eq!(cache.observer().hits.get(), 4);
+
+        let mut buf = [0; 64];
+        assert_eq!(cache.read(200.., &mut buf), 50);
+        assert_eq!(&buf[..50], &data()[200..]);
+    }
+
+    #[test]
+    fn evictions() {
+        let cache = cache();
+        let mut buf = [0; 250];
+        assert_eq!(cache.read(.., &mut buf), 250);
+        assert_eq!(&buf[..], &data()[..]);
+        assert_eq!(cache.observer().misses.get(), 16);
+        assert_eq!(cache.observer().evictions.get(), 8);
+
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 16);
+        assert_eq!(stats.evictions, 8);
+        assert_eq!(stats.blocks, 8);
+        assert_eq!(stats.valid_blocks, 8);
+        assert_eq!(stats.dirty_blocks, 0);
+        assert_eq!(stats.data_mem, 128);
+    }
+
+    #[test]
+    fn write_back() {
+        let cache = cache();
+        assert_eq!(cache.write(100, &[0xff; 40]), 40);
+        assert_eq!(cache.write(245, &[0xee; 10]), 10);
+        assert_eq!(cache.observer().writebacks.get(), 0);
+        assert_eq!(cache.stats().dirty_blocks, 4);
+
+        let mut expected = data();
+        expected[100..140].copy_from_slice(&[0xff; 40]);
+        expected.truncate(245);
+        expected.extend_from_slice(&[0xee; 10]);
+
+        let mut buf = [0; 255];
+        assert_eq!(cache.read(.., &mut buf), 255);
+        assert_eq!(&buf[..], &expected[..]);
+        assert!(cache.observer().writebacks.get() > 0);
+        assert_eq!(cache.into_source().into_inner(), expected);
+    }
+
+    #[test]
+    fn source_errors() {
+        let errors = Errors::default();
+        let writebacks = errors.writebacks.clone();
+        let cache: IOCache<FlakyConfig> =
+            IOCache::new_with_observer(Cursor::new(data()), 128, errors);
+        assert_eq!(cache.write(0, &[0xff; 16]), 16);
+        let mut buf = [0; 16];
+        for page in [2, 4, 6] {
+            assert_eq!(cache.read((page * 16)..((page + 1) * 16), &mut buf), 16);
+        }
+        // The dirty LRU block can't be written back, so it stays cached and the file doesn't grow.
+        assert_eq!(cache.write(256, &[1; 4]), 0);
+        assert_eq!(writebacks.get(), 1);
+        assert_eq!(cache.read(240.., &mut buf), 10);
+        let stats = cache.stats();
+        assert_eq!((stats.valid_blocks, stats.dirty_blocks), (5, 1));
+        assert_eq!(cache.read(0..16, &mut buf), 16);
+        assert_eq!(buf, [0xff; 16]);
+
+        assert_eq!(cache.read(48..64, &mut buf), 0);
+        assert_eq!(cache.observer().reads.get(), 1);
+        assert_eq!(cache.read(16..32, &mut buf), 16);
+        assert_eq!(&buf[..], &data()[16..32]);
+
+        // Dropping the cache reports the block it failed to write back.
+        let source = cache.into_source().into_inner();
+        assert_eq!(writebacks.get(), 2);
+        assert_eq!(&source[..16], &data()[..16]);
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let errors = Errors::default();
+        let writebacks = errors.writebacks.clone();
+        let cache: IOCache<FlakyConfig> =
+            IOCache::new_with_observer(Cursor::new(data()), 128, errors);
+        assert_eq!(cache.write(0, &[0xff; 16]), 16);
+        drop(cache);
+        assert_eq!(writebacks.get(), 1);
+
+        // `into_source` flushes once, not again when the cache goes away.
+        let errors = Errors::default();
+        let writebacks = errors.writebacks.clone();
+        let cache: IOCache<FlakyConfig> =
+            IOCache::new_with_observer(Cursor::new(data()), 128, errors);
+        assert_eq!(cache.write(0, &[0xff; 16]), 16);
+        drop(cache.into_source());
+        assert_eq!(writebacks.get(), 1);
+    }
+
+    #[test]
+    fn write_at_end() {
+        let errors = Errors::default();
+        let writebacks = errors.writebacks.clone();
+        let cache: IOCache<FlakyConfig> =
+            IOCache::new_with_observer(Cursor::new(data()), 128, errors);
+        assert_eq!(cache.write(u64::MAX - 3, &[0xff; 8]), 3);
+        assert_eq!(cache.write(u64::MAX, &[0xff; 8]), 0);
+        let mut buf = [0; 8];
+        assert_eq!(cache.read((u64::MAX - 3).., &mut buf), 3);
+        assert_eq!(&buf[..3], &[0xff; 3]);
+        assert_eq!(cache.stats().dirty_blocks, 1);
+        drop(cache.into_source());
+        assert_eq!(writebacks.get(), 1);
+    }
+
+    #[test]
+    fn invalidate() {
+        let cache = cache();
+        let mut buf = [0; 64];
+        assert_eq!(cache.read(0..64, &mut buf), 64);
+        assert_eq!(cache.write(16, &[0xff; 16]), 16);
+        cache.invalidate(10..40).unwrap();
+        let stats = cache.stats();
+        assert_eq!(stats.valid_blocks, 1);
+        assert_eq!(stats.writebacks, 1);
+
+        // Emptied frames are filled before evicting the remaining block.
+        assert_eq!(cache.read(64..128, &mut buf), 64);
+        assert_eq!(cache.stats().evictions, 0);
+        assert_eq!(cache.read(16..32, &mut buf), 16);
+        assert_eq!(&buf[..16], &[0xff; 16]);
+
+        // Huge ranges walk the cached frames instead of every page number in them.
+        cache.invalidate(0..u64::MAX).unwrap();
+        assert_eq!(cache.stats().valid_blocks, 0);
+    }
+
+    #[test]
+    fn clean_first() {
+        let cache: IOCache<CleanFirstConfig> = IOCache::new(Cursor::new(data()), 128);
+        assert_eq!(cache.write(0, &[0xff; 16]), 16);
+        let mut buf = [0; 250];
+        assert_eq!(cache.read(16.., &mut buf), 234);
+        // Clean blocks made room for the whole scan.
+        assert_eq!(cache.stats().evictions, 8);
+        assert_eq!(cache.stats().writebacks, 0);
+        assert_eq!(cache.stats().dirty_blocks, 1);
+        cache.flush().unwrap();
+        assert_eq!(&cache.into_source().into_inner()[..16], &[0xff; 16]);
+    }
+
+    #[test]
+    fn miss_cost() {
+        let cache: IOCache<CostConfig> = IOCache::new(Cursor::new(data()), 128);
+        let mut buf = [0; 250];
+        assert_eq!(cache.read(.., &mut buf), 250);
+        assert_eq!(&buf[..], &data()[..]);
+        // The slow pages outlive a scan through everything else.
+        let misses = cache.stats().misses;
+        assert_eq!(cache.read(0..32, &mut buf), 32);
+        assert_eq!(cache.stats().misses, misses);
+    }
+
+    #[test]
+    fn way_prediction() {
+        let cache: IOCache<PredictConfig> = IOCache::new(Cursor::new(data()), 128);
+        let mut buf = [0; 4];
+        for offset in 0..32 {
+            assert_eq!(cache.read(offset..(offset + 4), &mut buf), 4);
+            assert_eq!(&buf[..], &data()[offset as usize..(offset + 4) as usize]);
+        }
+        // Runs of reads in the same block hit the way each set used last.
+        let stats = cache.stats();
+        assert_eq!(stats.way_predictions, stats.accesses());
+        assert_eq!(stats.way_prediction_hits, stats.hits);
+        assert!(stats.way_prediction_ratio() > 0.9);
+    }
+
+    #[test]
+    fn for_each_replace() {
+        let cache = cache();
+        let mut sets = 0;
+        cache.for_each_replace(|_| sets += 1);
+        assert_eq!(sets, 2);
+    }
+
+    #[test]
+    fn admission() {
+        let cache: IOCache<AdmitConfig> = IOCache::new(Cursor::new(data()), 128);
+        let mut buf = [0; 250];
+        for _ in 0..2 {
+            assert_eq!(cache.read(.., &mut buf), 250);
+            assert_eq!(&buf[..], &data()[..]);
+        }
+        // Pages seen as often as the cached ones don't displace them.
+        let stats = cache.stats();
+        assert!(stats.admission_rejects > 0);
+        assert_eq!(stats.evictions + stats.admission_rejects, stats.misses - 8);
+        assert_eq!(stats.valid_blocks, 8);
+    }
 }
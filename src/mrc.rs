@@ -0,0 +1,283 @@
+// Miss-ratio curves from LRU stack (reuse) distances.
+//
+// Distances are computed Mattson-style: the stack distance of an access is the number of distinct
+// pages touched since the previous access to the same page, plus one. An LRU cache holding `n`
+// blocks hits exactly the accesses with a distance of at most `n`, so a single pass over the
+// accesses yields the miss ratio for every cache size at once.
+//
+// Instead of walking an explicit LRU stack, the most recent access time of each page is marked in
+// a Fenwick tree, making each access O(log n) in the number of distinct pages.
+
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::ops::RangeBounds;
+
+const MIN_CAPACITY: usize = 1024;
+
+pub struct ReuseDistance {
+    last: HashMap<u64, usize>,
+    tree: Vec<u32>,
+    now: usize,
+    hist: Vec<u64>,
+    cold: u64,
+    total: u64,
+}
+
+impl Default for ReuseDistance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReuseDistance {
+    pub fn new() -> Self {
+        Self {
+            last: HashMap::new(),
+            tree: vec![0; MIN_CAPACITY + 1],
+            now: 1,
+            hist: Vec::new(),
+            cold: 0,
+            total: 0,
+        }
+    }
+
+    pub fn from_trace<I: IntoIterator<Item = u64>>(trace: I) -> Self {
+        let mut ret = Self::new();
+        for page in trace {
+            ret.access(page);
+        }
+        ret
+    }
+
+    // Returns the stack distance of the access, or `None` if the page was never seen before.
+    pub fn access(&mut self, page: u64) -> Option<usize> {
+        self.total += 1;
+        let now = self.tick();
+        let ret = match self.last.insert(page, now) {
+            Some(prev) => {
+                let dist = (self.prefix(now - 1) - self.prefix(prev)) as usize + 1;
+                self.add(prev, -1);
+                if self.hist.len() < dist {
+                    self.hist.resize(dist, 0);
+                }
+                self.hist[dist - 1] += 1;
+                Some(dist)
+            }
+            None => {
+                self.cold += 1;
+                None
+            }
+        };
+        self.add(now, 1);
+        ret
+    }
+
+    // Records an access to every block overlapped by a byte range, as `IOCache::read` would with
+    // a buffer of `len` bytes. `len` also bounds a range without an end.
+    pub fn access_range<R: RangeBounds<u64>>(&mut self, range: R, len: usize, block_size: usize) {
+        let start = match range.start_bound() {
+            Bound::Included(s) => *s,
+            Bound::Excluded(s) => s.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => e.saturating_add(1),
+            Bound::Excluded(e) => *e,
+            Bound::Unbounded => u64::MAX,
+        };
+        let end = std::cmp::min(end, start.saturating_add(len as u64));
+        if start >= end {
+            return;
+        }
+        let block_size = block_size as u64;
+        for page in (start / block_size)..=((end - 1) / block_size) {
+            self.access(page);
+        }
+    }
+
+    pub fn accesses(&self) -> u64 {
+        self.total
+    }
+
+    pub fn cold_misses(&self) -> u64 {
+        self.cold
+    }
+
+    pub fn distinct_pages(&self) -> usize {
+        self.last.len()
+    }
+
+    // `histogram()[d - 1]` is the number of accesses with a stack distance of `d`.
+    pub fn histogram(&self) -> &[u64] {
+        &self.hist
+    }
+
+    pub fn curve(&self) -> MissRatioCurve {
+        let mut misses = vec![0; self.hist.len() + 1];
+        let mut acc = self.cold;
+        for (size, m) in misses.iter_mut().enumerate().rev() {
+            *m = acc;
+            if size > 0 {
+                acc += self.hist[size - 1];
+            }
+        }
+        MissRatioCurve {
+            misses,
+            total: self.total,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn tick(&mut self) -> usize {
+        if self.now == self.tree.len() {
+            self.compact();
+        }
+        let ret = self.now;
+        self.now += 1;
+        ret
+    }
+
+    // Renumbers the live timestamps to 1..=n and rebuilds the tree, so the tree only ever grows
+    // with the number of distinct pages and not with the number of accesses.
+    fn compact(&mut self) {
+        let mut live: Vec<(usize, u64)> = self.last.iter().map(|(p, t)| (*t, *p)).collect();
+        live.sort_unstable();
+        let cap = std::cmp::max(live.len() * 2, MIN_CAPACITY);
+        self.tree = vec![0; cap + 1];
+        for (idx, (_, page)) in live.iter().enumerate() {
+            self.last.insert(*page, idx + 1);
+        }
+        for idx in 1..=cap {
+            if idx <= live.len() {
+                self.tree[idx] += 1;
+            }
+            let parent = idx + (idx & idx.wrapping_neg());
+            if parent <= cap {
+                self.tree[parent] += self.tree[idx];
+            }
+        }
+        self.now = live.len() + 1;
+    }
+
+    fn add(&mut self, mut idx: usize, val: i32) {
+        while idx < self.tree.len() {
+            self.tree[idx] = (self.tree[idx] as i32 + val) as u32;
+            idx += idx & idx.wrapping_neg();
+        }
+    }
+
+    fn prefix(&self, mut idx: usize) -> u32 {
+        let mut ret = 0;
+        while idx > 0 {
+            ret += self.tree[idx];
+            idx -= idx & idx.wrapping_neg();
+        }
+        ret
+    }
+}
+
+pub struct MissRatioCurve {
+    misses: Vec<u64>,
+    total: u64,
+}
+
+impl MissRatioCurve {
+    pub fn accesses(&self) -> u64 {
+        self.total
+    }
+
+    // Number of misses an LRU cache of `blocks` blocks would have taken.
+    pub fn misses(&self, blocks: usize) -> u64 {
+        let idx = std::cmp::min(blocks, self.misses.len() - 1);
+        self.misses[idx]
+    }
+
+    pub fn miss_ratio(&self, blocks: usize) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.misses(blocks) as f64 / self.total as f64
+        }
+    }
+
+    pub fn hit_ratio(&self, blocks: usize) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            1.0 - self.miss_ratio(blocks)
+        }
+    }
+
+    // Miss ratio for a cache given `mem` bytes of block data, as passed to `IOCache::new`.
+    pub fn miss_ratio_for_mem(&self, mem: usize, block_size: usize) -> f64 {
+        self.miss_ratio(mem / block_size)
+    }
+
+    // Smallest cache size, in blocks, past which only cold misses remain.
+    pub fn working_set(&self) -> usize {
+        self.misses.len() - 1
+    }
+
+    // `(blocks, miss_ratio)` for every size from 0 up to the working set.
+    pub fn points(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        (0..self.misses.len()).map(move |blocks| (blocks, self.miss_ratio(blocks)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        let mut rd = ReuseDistance::new();
+        assert_eq!(rd.access(1), None);
+        assert_eq!(rd.access(2), None);
+        assert_eq!(rd.access(3), None);
+        assert_eq!(rd.access(3), Some(1));
+        assert_eq!(rd.access(1), Some(3));
+        assert_eq!(rd.access(2), Some(3));
+        assert_eq!(rd.access(2), Some(1));
+        assert_eq!(rd.histogram(), &[2, 0, 2]);
+    }
+
+    #[test]
+    fn loop_curve() {
+        let trace = (0..10_000u64).map(|i| i % 100);
+        let curve = ReuseDistance::from_trace(trace).curve();
+        assert_eq!(curve.working_set(), 100);
+        assert_eq!(curve.misses(0), 10_000);
+        assert_eq!(curve.misses(99), 10_000);
+        assert_eq!(curve.misses(100), 100);
+        assert_eq!(curve.misses(1_000_000), 100);
+    }
+
+    #[test]
+    fn compaction() {
+        let mut rd = ReuseDistance::new();
+        for i in 0..(MIN_CAPACITY as u64 * 10) {
+            rd.access(i % 7);
+        }
+        assert_eq!(rd.access(6), Some(7));
+        assert_eq!(rd.distinct_pages(), 7);
+    }
+
+    #[test]
+    fn ranges() {
+        let mut rd = ReuseDistance::new();
+        rd.access_range(10..300, 1024, 128);
+        rd.access_range(0..=127, 1024, 128);
+        assert_eq!(rd.accesses(), 4);
+        assert_eq!(rd.cold_misses(), 3);
+        assert_eq!(rd.histogram(), &[0, 0, 1]);
+
+        rd.access_range(200.., 100, 128);
+        assert_eq!(rd.accesses(), 6);
+        rd.access_range((u64::MAX - 10)..=u64::MAX, 1024, 128);
+        rd.access_range((Bound::Excluded(u64::MAX), Bound::Unbounded), 1024, 128);
+        assert_eq!(rd.accesses(), 7);
+    }
+}
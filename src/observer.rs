@@ -0,0 +1,39 @@
+// Hooks into the events of an `IOCache`, e.g. for forwarding them to a metrics or logging
+// pipeline. Every callback defaults to doing nothing, so `NoObserver` compiles away entirely.
+//
+// Callbacks are made while the lock of the set holding `page` is held, so they should be cheap.
+
+pub trait CacheObserver {
+    fn on_hit(&self, _page: u64) {}
+    fn on_miss(&self, _page: u64) {}
+    fn on_evict(&self, _page: u64, _dirty: bool) {}
+    fn on_writeback(&self, _page: u64, _result: &std::io::Result<()>) {}
+    fn on_source_error(&self, _page: u64, _error: &std::io::Error) {}
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct NoObserver;
+
+impl CacheObserver for NoObserver {}
+
+impl<T: CacheObserver + ?Sized> CacheObserver for std::sync::Arc<T> {
+    fn on_hit(&self, page: u64) {
+        (**self).on_hit(page)
+    }
+
+    fn on_miss(&self, page: u64) {
+        (**self).on_miss(page)
+    }
+
+    fn on_evict(&self, page: u64, dirty: bool) {
+        (**self).on_evict(page, dirty)
+    }
+
+    fn on_writeback(&self, page: u64, result: &std::io::Result<()>) {
+        (**self).on_writeback(page, result)
+    }
+
+    fn on_source_error(&self, page: u64, error: &std::io::Error) {
+        (**self).on_source_error(page, error)
+    }
+}
@@ -0,0 +1,181 @@
+// Renders cache stats in the Prometheus text exposition format, one sample per cache labeled
+// `cache="<name>"`. Serving the text is left to the caller.
+
+use super::stats::CacheStats;
+
+use std::fmt::Write;
+
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+    value: fn(&CacheStats) -> f64,
+}
+
+const METRICS: &[Metric] = &[
+    Metric {
+        name: "io_cache_hits_total",
+        help: "Block accesses served from the cache.",
+        kind: Kind::Counter,
+        value: |s| s.hits as f64,
+    },
+    Metric {
+        name: "io_cache_misses_total",
+        help: "Block accesses that had to be fetched from the source.",
+        kind: Kind::Counter,
+        value: |s| s.misses as f64,
+    },
+    Metric {
+        name: "io_cache_evictions_total",
+        help: "Valid blocks evicted to make room for another page.",
+        kind: Kind::Counter,
+        value: |s| s.evictions as f64,
+    },
+    Metric {
+        name: "io_cache_writebacks_total",
+        help: "Blocks written to the source.",
+        kind: Kind::Counter,
+        value: |s| s.writebacks as f64,
+    },
+    Metric {
+        name: "io_cache_source_errors_total",
+        help: "Failed reads from or writes to the source.",
+        kind: Kind::Counter,
+        value: |s| s.source_errors as f64,
+    },
+    Metric {
+        name: "io_cache_admission_rejects_total",
+        help: "Missed blocks served without being cached because admission declined them.",
+        kind: Kind::Counter,
+        value: |s| s.admission_rejects as f64,
+    },
+    Metric {
+        name: "io_cache_way_predictions_total",
+        help: "Set lookups that checked the way predictor first.",
+        kind: Kind::Counter,
+        value: |s| s.way_predictions as f64,
+    },
+    Metric {
+        name: "io_cache_way_prediction_hits_total",
+        help: "Set lookups the way predictor answered.",
+        kind: Kind::Counter,
+        value: |s| s.way_prediction_hits as f64,
+    },
+    Metric {
+        name: "io_cache_hit_ratio",
+        help: "Hits over all block accesses.",
+        kind: Kind::Gauge,
+        value: |s| s.hit_ratio(),
+    },
+    Metric {
+        name: "io_cache_blocks",
+        help: "Block frames in the cache.",
+        kind: Kind::Gauge,
+        value: |s| s.blocks as f64,
+    },
+    Metric {
+        name: "io_cache_valid_blocks",
+        help: "Block frames holding a page.",
+        kind: Kind::Gauge,
+        value: |s| s.valid_blocks as f64,
+    },
+    Metric {
+        name: "io_cache_occupancy_ratio",
+        help: "Valid blocks over all block frames.",
+        kind: Kind::Gauge,
+        value: |s| s.occupancy(),
+    },
+    Metric {
+        name: "io_cache_dirty_blocks",
+        help: "Blocks not yet written back to the source.",
+        kind: Kind::Gauge,
+        value: |s| s.dirty_blocks as f64,
+    },
+    Metric {
+        name: "io_cache_data_mem_bytes",
+        help: "Memory used for block data.",
+        kind: Kind::Gauge,
+        value: |s| s.data_mem as f64,
+    },
+    Metric {
+        name: "io_cache_meta_mem_bytes",
+        help: "Memory used for cache meta data.",
+        kind: Kind::Gauge,
+        value: |s| s.meta_mem as f64,
+    },
+    Metric {
+        name: "io_cache_async_queue_depth",
+        help: "Writes waiting in the asynchronous write queue.",
+        kind: Kind::Gauge,
+        value: |s| s.queue_depth as f64,
+    },
+];
+
+fn write_label<W: Write>(out: &mut W, value: &str) -> std::fmt::Result {
+    for c in value.chars() {
+        match c {
+            '\\' => out.write_str("\\\\")?,
+            '"' => out.write_str("\\\"")?,
+            '\n' => out.write_str("\\n")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+pub fn write<'a, W, I>(out: &mut W, caches: I) -> std::fmt::Result
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a str, &'a CacheStats)>,
+{
+    let caches: Vec<(&str, &CacheStats)> = caches.into_iter().collect();
+    for metric in METRICS {
+        let kind = match metric.kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        };
+        writeln!(out, "# HELP {} {}", metric.name, metric.help)?;
+        writeln!(out, "# TYPE {} {}", metric.name, kind)?;
+        for (name, stats) in caches.iter() {
+            write!(out, "{}{{cache=\"", metric.name)?;
+            write_label(out, name)?;
+            writeln!(out, "\"}} {}", (metric.value)(stats))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn render<'a, I: IntoIterator<Item = (&'a str, &'a CacheStats)>>(caches: I) -> String {
+    let mut ret = String::new();
+    let _ = write(&mut ret, caches);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposition() {
+        let a = CacheStats {
+            hits: 3,
+            misses: 1,
+            blocks: 8,
+            valid_blocks: 2,
+            ..Default::default()
+        };
+        let b = CacheStats::default();
+        let text = render(vec![("index", &a), ("data \"b\"", &b)]);
+        assert!(text.contains("# TYPE io_cache_hits_total counter\n"));
+        assert!(text.contains("io_cache_hits_total{cache=\"index\"} 3\n"));
+        assert!(text.contains("io_cache_hit_ratio{cache=\"index\"} 0.75\n"));
+        assert!(text.contains("io_cache_occupancy_ratio{cache=\"index\"} 0.25\n"));
+        assert!(text.contains("io_cache_hit_ratio{cache=\"data \\\"b\\\"\"} 0\n"));
+        assert_eq!(text.matches("# TYPE").count(), METRICS.len());
+    }
+}
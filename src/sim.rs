@@ -0,0 +1,209 @@
+// Trace replay: runs a sequence of page accesses against a replacement policy managing `frames`
+// block frames, without any data or source, to compare policies offline.
+//
+// `Optimal` is Bélády's MIN policy, evicting the page whose next use lies farthest in the future.
+// It needs the whole trace up front, so it only works here and gives the best hit ratio any
+// policy can reach on that trace.
+//
+// With no frames nothing is ever cached and every access is a miss.
+
+use super::detail::Replace;
+use super::stats::CacheStats;
+
+use std::collections::{BTreeSet, HashMap};
+
+const NULL: usize = usize::MAX;
+const NIL: u64 = u64::MAX;
+
+// What `replay` asks of a policy. Every `Replace` policy has it; `Optimal` only has this, as it
+// can't be built without its trace.
+pub trait Replay {
+    fn replace(&mut self, page: u64) -> usize;
+    fn record_access(&mut self, block_idx: usize);
+    fn record_insert(&mut self, block_idx: usize, page: u64);
+    fn admit(&mut self, page: u64, victim: usize) -> bool;
+}
+
+impl<R: Replace> Replay for R {
+    fn replace(&mut self, page: u64) -> usize {
+        Replace::replace(self, page)
+    }
+
+    fn record_access(&mut self, block_idx: usize) {
+        Replace::record_access(self, block_idx)
+    }
+
+    fn record_insert(&mut self, block_idx: usize, page: u64) {
+        Replace::record_insert(self, block_idx, page)
+    }
+
+    fn admit(&mut self, page: u64, victim: usize) -> bool {
+        Replace::admit(self, page, victim)
+    }
+}
+
+pub fn replay<R: Replay, I: IntoIterator<Item = u64>>(
+    mut policy: R,
+    frames: usize,
+    trace: I,
+) -> CacheStats {
+    let mut resident: HashMap<u64, usize> = HashMap::with_capacity(frames);
+    let mut pages = vec![NIL; frames];
+    let mut ret = CacheStats {
+        blocks: frames,
+        ..Default::default()
+    };
+    for page in trace {
+        if let Some(frame) = resident.get(&page) {
+            policy.record_access(*frame);
+            ret.hits += 1;
+            continue;
+        }
+        ret.misses += 1;
+        if frames == 0 {
+            continue;
+        }
+        let frame = policy.replace(page);
+        if !policy.admit(page, frame) {
+            ret.admission_rejects += 1;
+            continue;
+        }
+        if pages[frame] != NIL {
+            resident.remove(&pages[frame]);
+            ret.evictions += 1;
+        }
+        pages[frame] = page;
+        resident.insert(page, frame);
+        policy.record_insert(frame, page);
+    }
+    ret.valid_blocks = resident.len();
+    ret
+}
+
+pub fn replay_optimal(frames: usize, trace: &[u64]) -> CacheStats {
+    replay(
+        Optimal::with_trace(frames, trace),
+        frames,
+        trace.iter().copied(),
+    )
+}
+
+// Has to see exactly the accesses of the trace it was built with, in order, as `replay` does.
+pub struct Optimal {
+    next_use: Vec<usize>,
+    pos: usize,
+    frames: Vec<usize>,
+    used: usize,
+    // `(next use, frame)` of every used frame, farthest last.
+    order: BTreeSet<(usize, usize)>,
+}
+
+impl Optimal {
+    pub fn with_trace(frames: usize, trace: &[u64]) -> Self {
+        let mut next_use = vec![NULL; trace.len()];
+        let mut seen: HashMap<u64, usize> = HashMap::new();
+        for (pos, page) in trace.iter().enumerate().rev() {
+            if let Some(next) = seen.insert(*page, pos) {
+                next_use[pos] = next;
+            }
+        }
+        Self {
+            next_use,
+            pos: 0,
+            frames: vec![NULL; frames],
+            used: 0,
+            order: BTreeSet::new(),
+        }
+    }
+
+    fn advance(&mut self, frame: usize) {
+        assert!(
+            self.pos < self.next_use.len(),
+            "io_cache Optimal used past the end of its trace"
+        );
+        self.order.remove(&(self.frames[frame], frame));
+        self.frames[frame] = self.next_use[self.pos];
+        self.order.insert((self.frames[frame], frame));
+        self.pos += 1;
+    }
+}
+
+impl Replay for Optimal {
+    fn replace(&mut self, _: u64) -> usize {
+        let frame = if self.used < self.frames.len() {
+            self.used += 1;
+            self.used - 1
+        } else {
+            match self.order.iter().next_back() {
+                Some((_, frame)) => *frame,
+                None => 0,
+            }
+        };
+        self.advance(frame);
+        frame
+    }
+
+    fn record_access(&mut self, idx: usize) {
+        self.advance(idx);
+    }
+
+    fn record_insert(&mut self, _: usize, _: u64) {}
+
+    fn admit(&mut self, _: u64, _: usize) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::detail::{FAARC, FAFIFO, FALRU};
+    use super::*;
+
+    #[test]
+    fn belady() {
+        let trace = [1, 2, 3, 4, 1, 2, 5, 1, 2, 3, 4, 5];
+        let opt = replay_optimal(3, &trace);
+        assert_eq!(opt.misses, 7);
+        assert_eq!(opt.hits, 5);
+        assert_eq!(opt.valid_blocks, 3);
+        let lru = replay(FALRU::new(3), 3, trace.iter().copied());
+        assert_eq!(lru.misses, 10);
+    }
+
+    #[test]
+    fn upper_bound() {
+        let mut x = 1u64;
+        let trace: Vec<u64> = (0..5000)
+            .map(|_| {
+                x = x
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (x >> 33) % 64
+            })
+            .collect();
+        let opt = replay_optimal(16, &trace);
+        for other in [
+            replay(FALRU::new(16), 16, trace.iter().copied()),
+            replay(FAARC::new(16), 16, trace.iter().copied()),
+        ]
+        .iter()
+        {
+            assert!(opt.hits >= other.hits);
+            assert_eq!(other.accesses(), opt.accesses());
+        }
+    }
+    #[test]
+    fn no_frames() {
+        let trace = [1, 2, 1, 2];
+        for stats in [
+            replay_optimal(0, &trace),
+            replay(FAFIFO::new(0), 0, trace.iter().copied()),
+        ]
+        .iter()
+        {
+            assert_eq!(stats.misses, 4);
+            assert_eq!(stats.hits, 0);
+            assert_eq!(stats.valid_blocks, 0);
+        }
+    }
+}
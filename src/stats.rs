@@ -0,0 +1,47 @@
+// Point-in-time snapshot of an `IOCache`. The event counters are only kept when the config's
+// `EnableStats` is `True`; the block counts and memory figures are always filled in.
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub writebacks: u64,
+    pub source_errors: u64,
+    pub admission_rejects: u64,
+    pub way_prediction_hits: u64,
+    pub way_predictions: u64,
+    pub blocks: usize,
+    pub valid_blocks: usize,
+    pub dirty_blocks: usize,
+    pub data_mem: usize,
+    pub meta_mem: usize,
+    pub queue_depth: usize,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            accesses => self.hits as f64 / accesses as f64,
+        }
+    }
+
+    pub fn way_prediction_ratio(&self) -> f64 {
+        match self.way_predictions {
+            0 => 0.0,
+            predictions => self.way_prediction_hits as f64 / predictions as f64,
+        }
+    }
+
+    pub fn occupancy(&self) -> f64 {
+        match self.blocks {
+            0 => 0.0,
+            blocks => self.valid_blocks as f64 / blocks as f64,
+        }
+    }
+}